@@ -58,10 +58,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     while let Some(summary) = stream.next().await {
         let ob: Summary = summary?;
+        // Summaries and exchange events missed since the previous one, as this client or the server fell behind
+        let skipped = ob.skipped_summaries + ob.skipped_events;
         
         // Otherwise the spinner won't work
         pb_spread.set_position(0);
-        pb_spread.set_message(format!("{:.8}",ob.spread));
+        if skipped > 0 {
+            pb_spread.set_message(format!("{}\t(lagging, {} skipped)", ob.spread_decimal, skipped));
+        } else {
+            pb_spread.set_message(ob.spread_decimal.clone());
+        }
 
         let bid_max_len = ob.bids.iter().map(|l| l.amount).max_by(|a, b| a.partial_cmp(b).unwrap());
         let ask_max_len = ob.asks.iter().map(|l| l.amount).max_by(|a, b| a.partial_cmp(b).unwrap());
@@ -83,16 +89,12 @@ trait UpdateLevel {
 
 impl UpdateLevel for ProgressBar {
     fn update_level(&self, max_len: Option<f64>, level: &orderbook::Level) {
-        max_len.map(|len| {
+        if let Some(len) = max_len {
             self.set_length((100.0) as u64);
             self.set_position(((level.amount / len)*100.0) as u64)
-        });
-
-        match level.exchange.as_str() {
-            "Binance" => {self.set_prefix(format!("{} ", level.exchange));}
-            "Bitstamp" => {self.set_prefix(format!("{}", level.exchange));}
-            _ => {}
         }
-        self.set_message(format!("{:.8}\t{:.8}", level.price, level.amount));
+
+        self.set_prefix(format!("{:<8}", level.exchange));
+        self.set_message(format!("{}\t{}", level.price_decimal, level.amount_decimal));
     }
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use crate::model::{Decimal, OrderBookLevel};
+use std::collections::BTreeMap;
+
+/// Local copy of an orderbook, kept up to date from incremental updates.
+/// Levels are keyed by price, an update with a zero amount removes the level
+#[derive(Debug, Clone, Default)]
+pub struct LocalBook {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl LocalBook {
+    /// Builds the book from a full snapshot
+    pub fn from_snapshot(bids: &[OrderBookLevel], asks: &[OrderBookLevel]) -> Self {
+        let mut book = Self::default();
+        book.update_bids(bids);
+        book.update_asks(asks);
+        book
+    }
+
+    pub fn update_bids(&mut self, levels: &[OrderBookLevel]) {
+        update_side(&mut self.bids, levels);
+    }
+
+    pub fn update_asks(&mut self, levels: &[OrderBookLevel]) {
+        update_side(&mut self.asks, levels);
+    }
+
+    /// Drops the levels beyond `depth` on each side, for exchanges that stream a fixed depth
+    /// and don't send deletes for levels falling out of it
+    pub fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            self.bids.pop_first();
+        }
+        while self.asks.len() > depth {
+            self.asks.pop_last();
+        }
+    }
+
+    /// Bids sorted from the best (highest) price
+    pub fn bids(&self) -> Vec<OrderBookLevel> {
+        to_levels(self.bids.iter().rev())
+    }
+
+    /// Asks sorted from the best (lowest) price
+    pub fn asks(&self) -> Vec<OrderBookLevel> {
+        to_levels(self.asks.iter())
+    }
+}
+
+fn update_side(side: &mut BTreeMap<Decimal, Decimal>, levels: &[OrderBookLevel]) {
+    for level in levels {
+        if level.amount.is_zero() {
+            side.remove(&level.price);
+        } else {
+            side.insert(level.price, level.amount);
+        }
+    }
+}
+
+fn to_levels<'a>(levels: impl Iterator<Item = (&'a Decimal, &'a Decimal)>) -> Vec<OrderBookLevel> {
+    levels
+        .map(|(price, amount)| OrderBookLevel {
+            price: *price,
+            amount: *amount,
+        })
+        .collect()
+}
+
+/// A [LocalBook] kept over a subscription that recovers from a checksum mismatch or a sequence gap by
+/// resubscribing, the exchange answering with a fresh snapshot. The requests are queued for the driver,
+/// see [ExchangeConnector::outgoing](crate::ExchangeConnector::outgoing)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResubscribingBook {
+    book: Option<LocalBook>,
+    /// Whether a fresh snapshot was requested and is awaited, mismatches meanwhile don't request another
+    resubscribing: bool,
+    outgoing: Vec<String>,
+}
+
+impl ResubscribingBook {
+    /// Replaces the book with a snapshot, completing the resubscription if any
+    pub fn snapshot(&mut self, bids: &[OrderBookLevel], asks: &[OrderBookLevel]) -> &mut LocalBook {
+        self.resubscribing = false;
+        self.book.insert(LocalBook::from_snapshot(bids, asks))
+    }
+
+    /// Applies an update to the book. `None` until a snapshot arrives, the updates are useless without one
+    pub fn update(&mut self, bids: &[OrderBookLevel], asks: &[OrderBookLevel]) -> Option<&mut LocalBook> {
+        let book = self.book.as_mut()?;
+        book.update_bids(bids);
+        book.update_asks(asks);
+        Some(book)
+    }
+
+    pub fn book(&self) -> Option<&LocalBook> {
+        self.book.as_ref()
+    }
+
+    /// Drops the book and queues `requests`, e.g. an unsubscribe and a subscribe, unless a resubscription
+    /// is already awaiting its snapshot
+    pub fn resubscribe(&mut self, requests: impl IntoIterator<Item = String>) {
+        self.book = None;
+        if !self.resubscribing {
+            self.resubscribing = true;
+            self.outgoing.extend(requests);
+        }
+    }
+
+    /// Requests queued since the last call
+    pub fn outgoing(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// Forgets the book and the resubscription, before a (re)connection
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{exchanges, model::Decimal, BybitCategory, ConfigError, Exchange, ExchangeEndpoints, Instrument};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Trading rules of an instrument as listed by a venue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstrumentInfo {
+    /// Venue symbol, e.g. `BTCUSDT`
+    pub symbol: String,
+    /// Lowercase base asset, as named by the venue
+    pub base: String,
+    /// Lowercase quote asset, as named by the venue
+    pub quote: String,
+    /// Price increment
+    pub tick_size: Option<Decimal>,
+    /// Amount increment
+    pub lot_size: Option<Decimal>,
+    /// Status as reported by the venue, e.g. `TRADING`
+    pub status: String,
+    /// Whether the status allows trading, i.e. the orderbook is live
+    pub trading: bool,
+}
+
+impl InstrumentInfo {
+    /// Decimal places of the tick size, e.g. 2 for `0.01000000`
+    pub fn price_scale(&self) -> Option<u32> {
+        self.tick_size.map(|tick_size| tick_size.normalize().scale())
+    }
+
+    /// Decimal places of the lot size
+    pub fn amount_scale(&self) -> Option<u32> {
+        self.lot_size.map(|lot_size| lot_size.normalize().scale())
+    }
+}
+
+/// Increment of a value with the given number of decimals, e.g. `0.01` for 2
+pub(crate) fn decimals_to_size(decimals: u32) -> Decimal {
+    Decimal::new(1, decimals)
+}
+
+/// Instruments listed by a venue, as of `fetched_at` (seconds since the epoch)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VenueCatalogue {
+    pub fetched_at: u64,
+    /// REST base url the listing was fetched from, a cached listing is only reused for the same url
+    #[serde(default)]
+    pub rest_url: String,
+    /// Market listed, for the venues listing several, e.g. Bybit's `linear`. A cached listing is only reused
+    /// for the same market
+    #[serde(default)]
+    pub market: Option<String>,
+    pub instruments: Vec<InstrumentInfo>,
+}
+
+/// Instruments listed by each venue, built by [CatalogueFetcher]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Catalogue {
+    pub exchanges: HashMap<Exchange, VenueCatalogue>,
+}
+
+impl Catalogue {
+    /// Instruments listed by the exchange. `None` if its listing couldn't be fetched
+    pub fn instruments(&self, exchange: &Exchange) -> Option<&[InstrumentInfo]> {
+        self.exchanges.get(exchange).map(|venue| venue.instruments.as_slice())
+    }
+
+    /// The exchange's listing of an instrument, given in the venue's own assets (see [crate::SymbolRegistry])
+    pub fn get(&self, exchange: &Exchange, instrument: &Instrument) -> Option<&InstrumentInfo> {
+        let (base, quote) = exchanges::catalogue_assets(exchange, instrument);
+        self.instruments(exchange)?
+            .iter()
+            .find(|info| info.base == base && info.quote == quote)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        Ok(std::fs::write(path, serde_json::to_string(self)?)?)
+    }
+}
+
+/// Fetches the venues' instrument listings from their metadata endpoints, e.g. Binance `exchangeInfo`.
+/// Listings are cached to disk and reused until they are older than `max_age`
+#[derive(Debug, Clone)]
+pub struct CatalogueFetcher {
+    client: reqwest::Client,
+    rest_urls: HashMap<Exchange, String>,
+    bybit_category: BybitCategory,
+    cache: Option<PathBuf>,
+    max_age: Duration,
+}
+
+impl Default for CatalogueFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CatalogueFetcher {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            rest_urls: HashMap::new(),
+            bybit_category: BybitCategory::default(),
+            cache: Some(std::env::temp_dir().join("data-streamer-catalogue.json")),
+            max_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Overrides the REST base url an exchange's listing is fetched from, e.g. to test against a local stand-in
+    pub fn with_rest_url(mut self, exchange: Exchange, rest_url: impl Into<String>) -> Self {
+        self.rest_urls.insert(exchange, rest_url.into());
+        self
+    }
+
+    /// REST base urls of the endpoints' overrides, for the exchanges without an url of their own
+    pub(crate) fn with_endpoints(mut self, endpoints: &ExchangeEndpoints) -> Self {
+        for (exchange, venue) in &endpoints.endpoints {
+            if let Some(rest_url) = &venue.rest_url {
+                self.rest_urls.entry(exchange.clone()).or_insert_with(|| rest_url.clone());
+            }
+        }
+        self
+    }
+
+    /// Bybit market whose instruments are listed, the one its connectors stream
+    pub(crate) fn with_bybit_category(mut self, bybit_category: BybitCategory) -> Self {
+        self.bybit_category = bybit_category;
+        self
+    }
+
+    /// File the listings are cached in, `None` disables the cache
+    pub fn with_cache(mut self, cache: Option<PathBuf>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Listings of the given exchanges. Cached listings are used while fresh, a stale one is still
+    /// used when fetching fails. Exchanges without a listing are left out of the catalogue
+    pub async fn fetch(&self, exchanges: &[Exchange]) -> Catalogue {
+        let mut cached = match &self.cache {
+            Some(path) if path.exists() => Catalogue::load(path).unwrap_or_else(|err| {
+                warn!("Ignoring the instrument catalogue cache {:?}. Error: {}", path, err);
+                Catalogue::default()
+            }),
+            _ => Catalogue::default(),
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut catalogue = Catalogue::default();
+        let mut fetched = false;
+        for exchange in exchanges {
+            let Some(rest_url) = self
+                .rest_urls
+                .get(exchange)
+                .map(String::as_str)
+                .or_else(|| exchanges::rest_url(exchange))
+            else {
+                continue;
+            };
+            let market = exchanges::market(exchange, self.bybit_category);
+            // A listing fetched elsewhere, e.g. from Binance before switching to Binance.US, isn't the venue's
+            let cached_venue = cached
+                .exchanges
+                .remove(exchange)
+                .filter(|venue| venue.rest_url == rest_url && venue.market.as_deref() == market);
+            if let Some(venue) = &cached_venue {
+                if now.saturating_sub(venue.fetched_at) < self.max_age.as_secs() {
+                    catalogue.exchanges.insert(exchange.clone(), venue.clone());
+                    continue;
+                }
+            }
+            match exchanges::fetch_instruments(exchange, &self.client, rest_url, self.bybit_category).await {
+                Some(Ok(instruments)) => {
+                    info!("Fetched {} instruments listed on {}", instruments.len(), exchange);
+                    fetched = true;
+                    catalogue.exchanges.insert(
+                        exchange.clone(),
+                        VenueCatalogue {
+                            fetched_at: now,
+                            rest_url: rest_url.to_string(),
+                            market: market.map(str::to_string),
+                            instruments,
+                        },
+                    );
+                }
+                Some(Err(err)) => {
+                    warn!("Couldn't fetch the instruments listed on {}. Error: {:?}", exchange, err);
+                    if let Some(venue) = cached_venue {
+                        catalogue.exchanges.insert(exchange.clone(), venue);
+                    }
+                }
+                None => {}
+            }
+        }
+        if let (true, Some(path)) = (fetched, &self.cache) {
+            // Keep the cached listings of the exchanges not asked for this time
+            cached.exchanges.extend(catalogue.exchanges.clone());
+            if let Err(err) = cached.save(path) {
+                warn!("Couldn't cache the instrument catalogue to {:?}. Error: {}", path, err);
+            }
+        }
+        catalogue
+    }
+}
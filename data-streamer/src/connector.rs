@@ -0,0 +1,593 @@
+use crate::{
+    latest::LatestBooks,
+    model::{self, OrderBook, Trade},
+    reconnect::{Backoff, ReconnectPolicy, VenueStatus},
+    recorder::{ConnectionRecorder, Recorder},
+    Exchange, Instrument, StreamerError, VenueInstrument,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use log::*;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::SocketError;
+
+type WsStream = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// How the driver keeps a websocket connection alive
+/// Incoming `Ping` frames are answered in every case
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Heartbeat {
+    /// The exchange sends `Ping` frames and expects a `Pong` with the same payload
+    Pong,
+    /// The exchange expects the client to send a `Ping` frame every `interval`
+    Ping { interval: Duration },
+    /// The exchange expects an application level text message every `interval`, e.g. `{"op":"ping"}`
+    Message { interval: Duration, message: String },
+}
+
+/// What a connection streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionKind {
+    Books,
+    /// See [crate::Subscriptions::with_trades]
+    Trades,
+}
+
+/// Tells a connection apart from the other ones of its exchange, e.g. its trades or another shard of instruments
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Connection {
+    pub kind: ConnectionKind,
+    /// Instruments streamed, as their books and trades are labelled. Empty when the connector doesn't tell,
+    /// the connection then stands for all of the exchange's
+    pub instruments: Vec<String>,
+}
+
+impl Connection {
+    /// Whether the connection streams the books of `instrument`, i.e. its book is stale when the connection is
+    pub fn streams_books_of(&self, instrument: &str) -> bool {
+        self.kind == ConnectionKind::Books
+            && (self.instruments.is_empty() || self.instruments.iter().any(|streamed| streamed == instrument))
+    }
+}
+
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ConnectionKind::Books => "books",
+            ConnectionKind::Trades => "trades",
+        };
+        match self.instruments.is_empty() {
+            true => write!(f, "{}", kind),
+            false => write!(f, "{} of {}", kind, self.instruments.join(",")),
+        }
+    }
+}
+
+/// Everything a connection produces: orderbooks and its lifecycle,
+/// so consumers can tell a quiet market from a dead socket
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// The websocket connection got established
+    Connected { exchange: Exchange, connection: Connection },
+    /// The exchange confirmed the subscription to these channels
+    Subscribed { exchange: Exchange, channels: Vec<String> },
+    /// The connection was lost, the last books of its instruments are stale from now on
+    Disconnected {
+        exchange: Exchange,
+        connection: Connection,
+        reason: String,
+    },
+    /// The next connection attempt, the `attempt`th in a row, starts after `delay`
+    Reconnecting {
+        exchange: Exchange,
+        connection: Connection,
+        attempt: u32,
+        delay: Duration,
+    },
+    /// A book update, queued only when the books aren't [crate::Delivery::Conflated]
+    Book(OrderBook),
+    /// An execution, published by the trade connectors, see [crate::Subscriptions::with_trades]
+    Trade(Trade),
+    /// Something went wrong, the stream carries on regardless
+    Error(StreamerError),
+    /// The connection failed to stay up [ReconnectPolicy::failure_threshold] times in a row
+    Down {
+        exchange: Exchange,
+        connection: Connection,
+        failures: u32,
+    },
+    /// A connection reported as down has been healthy for [ReconnectPolicy::reset_after]
+    Up { exchange: Exchange, connection: Connection },
+    /// No book update of `instrument` arrived for `silent_for`, the connection gets closed and re-established.
+    /// `instrument` is `None` when the connector doesn't tell its instruments, none of them got an update then
+    Stale {
+        exchange: Exchange,
+        connection: Connection,
+        instrument: Option<String>,
+        silent_for: Duration,
+    },
+}
+
+/// Messages that couldn't be parsed and were skipped, per exchange
+pub type ParseErrorCounts = Arc<Mutex<HashMap<Exchange, u64>>>;
+
+/// When the last book update was received, per exchange and instrument
+pub type LastUpdates = Arc<Mutex<HashMap<(Exchange, String), Instant>>>;
+
+/// Circuit breaker state, per exchange and connection as every connection backs off on its own.
+/// Connections that haven't been healthy yet are missing
+pub type VenueStatuses = Arc<Mutex<HashMap<(Exchange, Connection), VenueStatus>>>;
+
+/// Where the driver sends what it gets out of a connection
+#[derive(Debug, Clone)]
+pub(crate) struct Outputs {
+    pub events: broadcast::Sender<StreamEvent>,
+    pub parse_errors: ParseErrorCounts,
+    pub statuses: VenueStatuses,
+    pub last_updates: LastUpdates,
+    /// Where the books go in place of `events` when they are conflated
+    pub latest: Option<LatestBooks>,
+}
+
+impl Outputs {
+    pub(crate) fn error(&self, err: StreamerError) {
+        error!("{}", err);
+        if let StreamerError::Parse { exchange, .. } = &err {
+            if let Ok(mut counts) = self.parse_errors.lock() {
+                *counts.entry(exchange.clone()).or_default() += 1;
+            }
+        }
+        self.send(StreamEvent::Error(err));
+    }
+
+    /// Records the connection's status, emitting an event when it goes down or comes back
+    fn status(&self, exchange: &Exchange, connection: &Connection, status: VenueStatus, failures: u32) {
+        let previous = match self.statuses.lock() {
+            Ok(mut statuses) => statuses.insert((exchange.clone(), connection.clone()), status),
+            Err(_) => return,
+        };
+        let event = match (previous, status) {
+            (Some(VenueStatus::Down), VenueStatus::Up) => StreamEvent::Up {
+                exchange: exchange.clone(),
+                connection: connection.clone(),
+            },
+            (previous, VenueStatus::Down) if previous != Some(VenueStatus::Down) => StreamEvent::Down {
+                exchange: exchange.clone(),
+                connection: connection.clone(),
+                failures,
+            },
+            _ => return,
+        };
+        warn!("{} {} is {:?}", exchange, connection, status);
+        self.send(event);
+    }
+
+    fn book(&self, ob: OrderBook) {
+        if let Ok(mut last_updates) = self.last_updates.lock() {
+            last_updates.insert((ob.exchange.clone(), ob.instrument.clone()), Instant::now());
+        }
+        match &self.latest {
+            Some(latest) => latest.publish(ob),
+            None => self.send(StreamEvent::Book(ob)),
+        }
+    }
+
+    pub(crate) fn send(&self, event: StreamEvent) {
+        let _ = self.events.send(event);
+    }
+}
+
+/// How the driver runs a connector
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DriverConfig {
+    pub policy: ReconnectPolicy,
+    /// The connection is re-established when no book update arrives within it
+    pub stale_after: Option<Duration>,
+    /// Instruments the connector streams, keyed by the native compact symbol its books and trades are labelled
+    /// with. They get relabelled with the canonical instrument and the substitutions made
+    pub symbols: HashMap<String, VenueInstrument>,
+    /// `(price, amount)` decimal places of each native symbol, from the venue's tick and lot sizes.
+    /// Levels are padded to them so every price of an instrument is quoted with the same scale
+    pub scales: HashMap<String, (u32, u32)>,
+    /// Records the connections' frames, `None` when the exchange isn't recorded
+    pub recorder: Option<Recorder>,
+}
+
+impl DriverConfig {
+    /// Identity of the connector's connection, its instruments labelled with their canonical symbol
+    pub(crate) fn connection(&self, connector: &dyn ExchangeConnector) -> Connection {
+        let instruments = connector
+            .instruments()
+            .iter()
+            .map(|instrument| {
+                let native = instrument.get_symbol_compact();
+                match self.symbols.get(&native) {
+                    Some(venue_instrument) => venue_instrument.canonical.get_symbol_compact(),
+                    None => native,
+                }
+            })
+            .collect();
+        Connection {
+            kind: connector.kind(),
+            instruments,
+        }
+    }
+
+    fn relabel(&self, mut ob: OrderBook) -> OrderBook {
+        if let Some((price_scale, amount_scale)) = self.scales.get(&ob.instrument) {
+            for level in ob.bids.iter_mut().chain(ob.asks.iter_mut()) {
+                level.pad_scale(*price_scale, *amount_scale);
+            }
+        }
+        if let Some(venue_instrument) = self.symbols.get(&ob.instrument) {
+            ob.instrument = venue_instrument.canonical.get_symbol_compact();
+            ob.substitutions = venue_instrument.substitutions.clone();
+        }
+        ob
+    }
+
+    fn relabel_trade(&self, mut trade: Trade) -> Trade {
+        if let Some((price_scale, amount_scale)) = self.scales.get(&trade.instrument) {
+            model::pad_scale(&mut trade.price, *price_scale);
+            model::pad_scale(&mut trade.amount, *amount_scale);
+        }
+        if let Some(venue_instrument) = self.symbols.get(&trade.instrument) {
+            trade.instrument = venue_instrument.canonical.get_symbol_compact();
+            trade.substitutions = venue_instrument.substitutions.clone();
+        }
+        trade
+    }
+}
+
+/// Everything that is exchange specific about streaming an orderbook.
+/// Implement it to add a new venue, the reconnect loop is shared and lives in [run_connector]
+#[async_trait]
+pub trait ExchangeConnector: Send {
+    /// Exchange every produced [OrderBook] is tagged with
+    fn exchange(&self) -> Exchange;
+
+    /// Websocket url to connect to
+    fn endpoint(&self) -> String;
+
+    /// What the connection streams, tagging its lifecycle events
+    fn kind(&self) -> ConnectionKind {
+        ConnectionKind::Books
+    }
+
+    /// Instruments the connection streams, tagging its lifecycle events. Empty when not implemented,
+    /// the connection then stands for all of the exchange's
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![]
+    }
+
+    /// Url to connect to, resolved before every (re)connection. Defaults to [ExchangeConnector::endpoint],
+    /// exchanges handing out the websocket endpoint through a REST handshake override it.
+    /// `None` if the handshake failed, the connection is then retried with backoff
+    async fn resolve_endpoint(&mut self) -> Option<String> {
+        Some(self.endpoint())
+    }
+
+    /// Messages sent right after the connection is established.
+    /// Exchanges that subscribe through the url don't need any
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Called before every (re)connection. Connectors that maintain a local book drop it here,
+    /// updates missed while disconnected make it unusable
+    fn reset(&mut self) {}
+
+    /// Transforms a text message into the normalized [OrderBook].
+    /// Returns `None` for messages that don't carry orderbook data. Parse errors skip the message,
+    /// [StreamerError::ReconnectRequested] drops the connection.
+    /// It's async so connectors can fetch REST snapshots to synchronise incremental streams
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError>;
+
+    /// Decodes a binary message into the text handed to [ExchangeConnector::parse], e.g. decompressing it.
+    /// Binary messages are ignored unless implemented
+    fn decode_binary(&self, _msg: &[u8]) -> Result<Option<String>, StreamerError> {
+        Ok(None)
+    }
+
+    /// Messages the connector wants sent, e.g. a resubscription after a checksum mismatch.
+    /// Drained by the driver after every parsed message
+    fn outgoing(&mut self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Events the connector recognised in the last parsed message, e.g. subscription acks or [StreamEvent::Trade].
+    /// Drained by the driver after every parsed message
+    fn events(&mut self) -> Vec<StreamEvent> {
+        vec![]
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Pong
+    }
+}
+
+/// Connects the given connector to its websocket stream. Handles reconnection following the [ReconnectPolicy]
+/// Sends orderbooks and lifecycle events back to the channel from which it's called
+pub(crate) async fn run_connector(mut connector: Box<dyn ExchangeConnector>, config: DriverConfig, outputs: Outputs) {
+    let exchange = connector.exchange();
+    let connection = config.connection(connector.as_ref());
+    let mut backoff = Backoff::new(config.policy.clone());
+    loop {
+        connector.reset();
+        match connector.resolve_endpoint().await {
+            Some(endpoint) => match connect_async(&endpoint).await {
+                Ok((stream, _response)) => {
+                    info!("Initialized {} stream", exchange);
+                    backoff.connected();
+                    outputs.send(StreamEvent::Connected {
+                        exchange: exchange.clone(),
+                        connection: connection.clone(),
+                    });
+                    let recording = config
+                        .recorder
+                        .as_ref()
+                        .map(|recorder| recorder.connection(exchange.clone(), &endpoint));
+                    let reason = match consume(
+                        connector.as_mut(),
+                        &connection,
+                        stream,
+                        &config,
+                        &outputs,
+                        &mut backoff,
+                        recording.as_ref(),
+                    )
+                    .await
+                    {
+                        Ok(()) => "closed by the exchange".to_string(),
+                        Err(err) => {
+                            let reason = err.to_string();
+                            outputs.error(err);
+                            reason
+                        }
+                    };
+                    if let Some(recording) = &recording {
+                        recording.disconnected(&reason);
+                    }
+                    outputs.send(StreamEvent::Disconnected {
+                        exchange: exchange.clone(),
+                        connection: connection.clone(),
+                        reason,
+                    });
+                }
+                Err(err) => outputs.error(StreamerError::Connect {
+                    exchange: exchange.clone(),
+                    reason: err.to_string(),
+                }),
+            },
+            None => outputs.error(StreamerError::Connect {
+                exchange: exchange.clone(),
+                reason: "couldn't resolve the endpoint".to_string(),
+            }),
+        }
+        let Some(sleep) = backoff.failed() else {
+            error!("{} failed {} times in a row, giving up", exchange, backoff.failures());
+            outputs.status(&exchange, &connection, VenueStatus::Down, backoff.failures());
+            return;
+        };
+        if backoff.tripped() {
+            outputs.status(&exchange, &connection, VenueStatus::Down, backoff.failures());
+        }
+        warn!("{} {} stream disconnected, re-connecting. Sleep:{:?}", exchange, connection, sleep);
+        outputs.send(StreamEvent::Reconnecting {
+            exchange: exchange.clone(),
+            connection: connection.clone(),
+            attempt: backoff.failures(),
+            delay: sleep,
+        });
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+/// Reads from an established connection until it fails, gets closed or goes stale.
+/// Returns the error that ended the connection, if any
+async fn consume(
+    connector: &mut dyn ExchangeConnector,
+    connection: &Connection,
+    mut stream: WsStream,
+    config: &DriverConfig,
+    outputs: &Outputs,
+    backoff: &mut Backoff,
+    recording: Option<&ConnectionRecorder>,
+) -> Result<(), StreamerError> {
+    let exchange = connector.exchange();
+    let protocol_error = |err: SocketError| StreamerError::Protocol {
+        exchange: exchange.clone(),
+        reason: err.to_string(),
+    };
+    for msg in connector.subscribe_messages() {
+        send(&mut stream, Message::Text(msg), recording).await.map_err(protocol_error)?;
+        info!("Sent subscription message to {}", exchange);
+    }
+
+    let heartbeat = connector.heartbeat();
+    let mut heartbeat_interval = match &heartbeat {
+        Heartbeat::Ping { interval } | Heartbeat::Message { interval, .. } => Some(tokio::time::interval(*interval)),
+        Heartbeat::Pong => None,
+    };
+    // A half-open connection never errors, it just stops delivering. An instrument can stop on its own too,
+    // e.g. a shard's subscription silently dropped, so every instrument is watched
+    let stale_after = config.stale_after;
+    let connected_at = Instant::now();
+    let mut last_book = connected_at;
+
+    loop {
+        let (stalest, stalest_update) = match stale_after {
+            Some(_) => stalest(outputs, &exchange, connection, connected_at, last_book),
+            None => (None, last_book),
+        };
+        tokio::select! {
+            event = stream.next() => {
+                let received_ts = Utc::now();
+                if let (Some(recording), Some(Ok(msg))) = (recording, &event) {
+                    recording.received(received_ts, msg);
+                }
+                if backoff.received() {
+                    outputs.status(&exchange, connection, VenueStatus::Up, 0);
+                }
+                match event {
+                    Some(Ok(Message::Text(msg))) => {
+                        if handle_text(connector, &msg, received_ts, &mut stream, config, outputs, recording).await? {
+                            last_book = Instant::now();
+                        }
+                    }
+                    Some(Ok(Message::Binary(msg))) => {
+                        match connector.decode_binary(&msg) {
+                            Ok(Some(msg)) => {
+                                if handle_text(connector, &msg, received_ts, &mut stream, config, outputs, recording).await? {
+                                    last_book = Instant::now();
+                                }
+                            }
+                            Ok(None) => warn!("Received binary message from {}. Msg: {:?}", exchange, msg),
+                            Err(err) => outputs.error(err),
+                        }
+                    }
+                    Some(Ok(Message::Ping(msg))) => {
+                        info!("Received PING message from {}", exchange);
+                        send(&mut stream, Message::Pong(msg), recording).await.map_err(protocol_error)?;
+                        info!("Sent PONG message to {}", exchange);
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        info!("{} closed the connection. Frame: {:?}", exchange, frame);
+                        return Ok(());
+                    }
+                    Some(Ok(msg)) => {
+                        warn!("Received a non-handled message from {}, ignoring. Msg: {:?}", exchange, msg);
+                    }
+                    Some(Err(err)) => return Err(protocol_error(err)),
+                    None => return Ok(()),
+                }
+            },
+            _ = async { heartbeat_interval.as_mut().unwrap().tick().await }, if heartbeat_interval.is_some() => {
+                let msg = match &heartbeat {
+                    Heartbeat::Message { message, .. } => Message::Text(message.clone()),
+                    _ => Message::Ping(vec![]),
+                };
+                send(&mut stream, msg, recording).await.map_err(protocol_error)?;
+            }
+            _ = async {
+                tokio::time::sleep_until((stalest_update + stale_after.unwrap()).into()).await
+            }, if stale_after.is_some() => {
+                let silent_for = stalest_update.elapsed();
+                outputs.send(StreamEvent::Stale {
+                    exchange: exchange.clone(),
+                    connection: connection.clone(),
+                    instrument: stalest.clone(),
+                    silent_for,
+                });
+                return Err(StreamerError::Stale {
+                    exchange,
+                    instrument: stalest,
+                    silent_for,
+                });
+            }
+        }
+    }
+}
+
+/// The connection's instrument whose book was updated the longest ago and when, no earlier than the connection
+/// got established. Without instruments to tell apart, it's the connection's `last_book` update
+fn stalest(
+    outputs: &Outputs,
+    exchange: &Exchange,
+    connection: &Connection,
+    since: Instant,
+    last_book: Instant,
+) -> (Option<String>, Instant) {
+    if connection.instruments.is_empty() {
+        return (None, last_book);
+    }
+    let Ok(last_updates) = outputs.last_updates.lock() else {
+        return (None, last_book);
+    };
+    connection
+        .instruments
+        .iter()
+        .map(|instrument| {
+            let last_update = last_updates
+                .get(&(exchange.clone(), instrument.clone()))
+                .map_or(since, |last_update| (*last_update).max(since));
+            (Some(instrument.clone()), last_update)
+        })
+        .min_by_key(|(_, last_update)| *last_update)
+        .unwrap_or((None, since))
+}
+
+/// Parses a text message and forwards what the connector produced: the orderbook and events to the channel,
+/// the queued messages to the exchange. Parse errors are reported and skipped, any other error ends the connection.
+/// Returns whether the message produced an orderbook
+async fn handle_text(
+    connector: &mut dyn ExchangeConnector,
+    msg: &str,
+    received_ts: DateTime<Utc>,
+    stream: &mut WsStream,
+    config: &DriverConfig,
+    outputs: &Outputs,
+    recording: Option<&ConnectionRecorder>,
+) -> Result<bool, StreamerError> {
+    let book = publish(connector, msg, received_ts, config, outputs).await?;
+    for msg in connector.outgoing() {
+        send(stream, Message::Text(msg), recording).await.map_err(|err| StreamerError::Protocol {
+            exchange: connector.exchange(),
+            reason: err.to_string(),
+        })?;
+    }
+    Ok(book)
+}
+
+/// Parses a text message and sends the orderbook and events the connector produced to the channel.
+/// Parse errors are reported and skipped, any other error is returned. Returns whether the message
+/// produced an orderbook
+pub(crate) async fn publish(
+    connector: &mut dyn ExchangeConnector,
+    msg: &str,
+    received_ts: DateTime<Utc>,
+    config: &DriverConfig,
+    outputs: &Outputs,
+) -> Result<bool, StreamerError> {
+    let parsed = connector.parse(msg).await;
+    for event in connector.events() {
+        match event {
+            StreamEvent::Trade(mut trade) => {
+                trade.received_ts = received_ts;
+                outputs.send(StreamEvent::Trade(config.relabel_trade(trade)));
+            }
+            event => outputs.send(event),
+        }
+    }
+    let book = match parsed {
+        Ok(Some(mut ob)) => {
+            ob.received_ts = received_ts;
+            outputs.book(config.relabel(ob));
+            true
+        }
+        Ok(None) => false,
+        Err(err @ StreamerError::Parse { .. }) => {
+            outputs.error(err);
+            false
+        }
+        Err(err) => return Err(err),
+    };
+    Ok(book)
+}
+
+/// Sends a frame to the exchange, recording it when the connection is recorded
+async fn send(stream: &mut WsStream, msg: Message, recording: Option<&ConnectionRecorder>) -> Result<(), SocketError> {
+    if let Some(recording) = recording {
+        recording.sent(&msg);
+    }
+    stream.send(msg).await
+}
@@ -0,0 +1,125 @@
+use crate::{exchanges::binance, ConfigError, Exchange};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+
+/// Prefix of the environment variables overriding the endpoints, e.g. `UOS_BINANCE_WS_URL`
+pub const ENV_PREFIX: &str = "UOS";
+
+/// Base urls an exchange is reached at. Unset ones are the venue's production urls
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VenueEndpoints {
+    /// Websocket url the connectors connect to. Binance appends the `/stream` path to it and Bybit the category,
+    /// the other venues use it as is. KuCoin uses it in place of the endpoint handed out by its handshake
+    pub ws_url: Option<String>,
+    /// REST base url the listings, snapshots and handshakes are fetched from
+    pub rest_url: Option<String>,
+}
+
+impl VenueEndpoints {
+    /// Binance.US, a separate venue with listings of its own
+    pub fn binance_us() -> Self {
+        Self {
+            ws_url: Some(binance::BINANCE_US_WS_URL.to_string()),
+            rest_url: Some(binance::BINANCE_US_REST_URL.to_string()),
+        }
+    }
+
+    /// Binance's spot testnet
+    pub fn binance_testnet() -> Self {
+        Self {
+            ws_url: Some(binance::BINANCE_TESTNET_WS_URL.to_string()),
+            rest_url: Some(binance::BINANCE_TESTNET_REST_URL.to_string()),
+        }
+    }
+
+    /// Applies the websocket url override to a connector, e.g. with `BinanceConnector::with_ws_url`
+    pub(crate) fn ws<C>(&self, connector: C, with_ws_url: impl FnOnce(C, String) -> C) -> C {
+        match &self.ws_url {
+            Some(ws_url) => with_ws_url(connector, ws_url.clone()),
+            None => connector,
+        }
+    }
+
+    /// Applies the REST url override to a connector, e.g. with `BinanceDiffConnector::with_rest_url`
+    pub(crate) fn rest<C>(&self, connector: C, with_rest_url: impl FnOnce(C, String) -> C) -> C {
+        match &self.rest_url {
+            Some(rest_url) => with_rest_url(connector, rest_url.clone()),
+            None => connector,
+        }
+    }
+}
+
+/// Endpoints of the exchanges that aren't reached at their production urls, e.g. a testnet,
+/// a regional variant or a local stand-in. Loadable from a TOML file, e.g. for Binance.US
+/// ```toml
+/// [endpoints.Binance]
+/// ws_url = "wss://stream.binance.us:9443"
+/// rest_url = "https://api.binance.us"
+/// ```
+/// or from the `UOS_<EXCHANGE>_WS_URL` and `UOS_<EXCHANGE>_REST_URL` environment variables
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExchangeEndpoints {
+    #[serde(default)]
+    pub endpoints: HashMap<Exchange, VenueEndpoints>,
+}
+
+impl ExchangeEndpoints {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Overrides of the built-in exchanges set in the environment
+    pub fn from_env() -> Self {
+        Self::default().with_env()
+    }
+
+    /// These endpoints overridden by the ones set in the environment
+    pub fn with_env(mut self) -> Self {
+        let built_in = [
+            Exchange::Binance,
+            Exchange::Bitstamp,
+            Exchange::Kraken,
+            Exchange::Coinbase,
+            Exchange::Okx,
+            Exchange::Bybit,
+            Exchange::Htx,
+            Exchange::Kucoin,
+        ];
+        for exchange in built_in {
+            let var = |url: &str| {
+                std::env::var(format!("{}_{}_{}", ENV_PREFIX, exchange.to_string().to_uppercase(), url))
+                    .ok()
+                    .filter(|value| !value.is_empty())
+            };
+            let (ws_url, rest_url) = (var("WS_URL"), var("REST_URL"));
+            if ws_url.is_none() && rest_url.is_none() {
+                continue;
+            }
+            let endpoints = self.endpoints.entry(exchange).or_default();
+            endpoints.ws_url = ws_url.or(endpoints.ws_url.take());
+            endpoints.rest_url = rest_url.or(endpoints.rest_url.take());
+        }
+        self
+    }
+
+    /// Reaches the exchange at these endpoints, e.g. [VenueEndpoints::binance_us]
+    pub fn with_venue(mut self, exchange: Exchange, endpoints: VenueEndpoints) -> Self {
+        self.endpoints.insert(exchange, endpoints);
+        self
+    }
+
+    pub fn with_ws_url(mut self, exchange: Exchange, ws_url: impl Into<String>) -> Self {
+        self.endpoints.entry(exchange).or_default().ws_url = Some(ws_url.into());
+        self
+    }
+
+    pub fn with_rest_url(mut self, exchange: Exchange, rest_url: impl Into<String>) -> Self {
+        self.endpoints.entry(exchange).or_default().rest_url = Some(rest_url.into());
+        self
+    }
+
+    /// The exchange's overrides, empty when it's reached at its production urls
+    pub fn get(&self, exchange: &Exchange) -> VenueEndpoints {
+        self.endpoints.get(exchange).cloned().unwrap_or_default()
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{Exchange, Instrument};
+use std::{fmt::Display, time::Duration};
+use thiserror::Error;
+
+/// Errors raised while streaming from an exchange. None of them stops the stream by itself:
+/// connection failures are retried following the [crate::ReconnectPolicy] and unparsable messages are skipped
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StreamerError {
+    /// The websocket connection, or the handshake resolving its endpoint, failed
+    #[error("couldn't connect to {exchange}: {reason}")]
+    Connect { exchange: Exchange, reason: String },
+    /// The websocket failed while connected
+    #[error("{exchange} websocket error: {reason}")]
+    Protocol { exchange: Exchange, reason: String },
+    /// A message couldn't be parsed. `payload` is the raw message
+    #[error("couldn't parse {exchange} message: {reason}. Payload: {payload}")]
+    Parse {
+        exchange: Exchange,
+        reason: String,
+        payload: String,
+    },
+    /// The exchange answered a subscription with an error
+    #[error("{exchange} rejected the subscription: {reason}")]
+    SubscriptionRejected { exchange: Exchange, reason: String },
+    /// The exchange asked the client to reconnect, e.g. before a maintenance
+    #[error("{exchange} requested a reconnection")]
+    ReconnectRequested { exchange: Exchange },
+    /// No book update of `instrument` arrived within the staleness threshold, the connection is assumed dead
+    #[error(
+        "no {exchange} book update{} for {silent_for:?}",
+        .instrument.as_ref().map(|instrument| format!(" of {}", instrument)).unwrap_or_default()
+    )]
+    Stale {
+        exchange: Exchange,
+        instrument: Option<String>,
+        silent_for: Duration,
+    },
+}
+
+impl StreamerError {
+    pub fn parse(exchange: Exchange, reason: impl Display, payload: &str) -> Self {
+        StreamerError::Parse {
+            exchange,
+            reason: reason.to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    pub fn subscription_rejected(exchange: Exchange, reason: impl Display) -> Self {
+        StreamerError::SubscriptionRejected {
+            exchange,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn exchange(&self) -> &Exchange {
+        match self {
+            StreamerError::Connect { exchange, .. }
+            | StreamerError::Protocol { exchange, .. }
+            | StreamerError::Parse { exchange, .. }
+            | StreamerError::SubscriptionRejected { exchange, .. }
+            | StreamerError::ReconnectRequested { exchange }
+            | StreamerError::Stale { exchange, .. } => exchange,
+        }
+    }
+}
+
+/// Errors loading a configuration file
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("couldn't read the config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid config file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid JSON file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Errors starting the streams, see [crate::Subscriptions::init]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InitError {
+    /// The exchanges don't list these instruments, or have them halted
+    #[error("instruments not listed: {}", list_unlisted(.0))]
+    Unlisted(Vec<(Exchange, Instrument)>),
+}
+
+fn list_unlisted(unlisted: &[(Exchange, Instrument)]) -> String {
+    unlisted
+        .iter()
+        .map(|(exchange, instrument)| format!("{} on {}", instrument.get_symbol_slash(), exchange))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
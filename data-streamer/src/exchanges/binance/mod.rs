@@ -1 +1,471 @@
-pub mod model;
\ No newline at end of file
+pub mod model;
+
+use crate::{
+    book::LocalBook,
+    catalogue::InstrumentInfo,
+    connector::{ConnectionKind, ExchangeConnector, StreamEvent},
+    model::{OrderBook, Trade},
+    reconnect::{SnapshotRetry, SNAPSHOT_TIMEOUT},
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::*;
+use model::{BinanceDepthUpdate, BinanceExchangeInfo, BinanceOrderBook, BinanceStreamMessage, BinanceTrade};
+use std::collections::HashMap;
+
+pub const BINANCE_REST_URL: &str = "https://api.binance.com";
+pub const BINANCE_WS_URL: &str = "wss://stream.binance.com:9443";
+pub const BINANCE_US_REST_URL: &str = "https://api.binance.us";
+pub const BINANCE_US_WS_URL: &str = "wss://stream.binance.us:9443";
+pub const BINANCE_TESTNET_REST_URL: &str = "https://testnet.binance.vision";
+pub const BINANCE_TESTNET_WS_URL: &str = "wss://stream.testnet.binance.vision";
+
+/// Streams a single connection carries, the instruments are sharded across connections beyond it. Binance accepts
+/// up to 1024 per connection, but the streams are all named in the connect url, which has to stay within the usual
+/// request line limits: 100 streams of the longest symbols make a url of about 3KB
+pub const BINANCE_MAX_STREAMS: usize = 100;
+
+/// Instruments listed in `/api/v3/exchangeInfo`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let info: BinanceExchangeInfo = client
+        .get(format!("{}/api/v3/exchangeInfo", rest_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(info.symbols.into_iter().map(Into::into).collect())
+}
+
+/// Combined streams url, multiplexing the `stream` of every instrument over one connection
+fn combined_endpoint(ws_url: &str, instruments: &[Instrument], stream: &str) -> String {
+    let streams: Vec<String> = instruments
+        .iter()
+        .map(|instrument| format!("{}@{}", instrument.get_symbol_compact(), stream))
+        .collect();
+    format!("{}/stream?streams={}", ws_url, streams.join("/"))
+}
+
+/// Instrument a combined stream message belongs to, `stream` is e.g. `btcusdt@depth20@100ms`
+fn find_instrument<'a>(instruments: &'a [Instrument], stream: &str) -> Option<&'a Instrument> {
+    let symbol = stream.split('@').next().unwrap_or_default();
+    instruments
+        .iter()
+        .find(|instrument| instrument.get_symbol_compact() == symbol)
+}
+
+/// Streams the `@depth20@100ms` partial book, a 20 level snapshot every 100ms
+#[derive(Debug, Clone)]
+pub struct BinanceConnector {
+    instruments: Vec<Instrument>,
+    ws_url: String,
+}
+
+impl BinanceConnector {
+    pub fn new(instruments: Vec<Instrument>) -> Self {
+        Self {
+            instruments,
+            ws_url: BINANCE_WS_URL.to_string(),
+        }
+    }
+
+    /// Overrides the websocket base url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Binance
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        self.instruments.clone()
+    }
+
+    fn endpoint(&self) -> String {
+        combined_endpoint(&self.ws_url, &self.instruments, "depth20@100ms")
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let raw_ob = serde_json::from_str::<BinanceStreamMessage<BinanceOrderBook>>(msg)
+            .map_err(|err| StreamerError::parse(Exchange::Binance, err, msg))?;
+        let Some(instrument) = find_instrument(&self.instruments, &raw_ob.stream) else {
+            warn!("Received Binance message for a non-subscribed stream {}", raw_ob.stream);
+            return Ok(None);
+        };
+        Ok(Some(OrderBook::from((
+            Exchange::Binance,
+            instrument.get_symbol_compact(),
+            raw_ob.data,
+        ))))
+    }
+}
+
+/// Local book of one instrument of a [BinanceDiffConnector]
+#[derive(Debug, Clone, Default)]
+struct BinanceDiffBook {
+    book: Option<LocalBook>,
+    last_update_id: usize,
+    /// Paces the snapshot requests while the book isn't synchronised
+    retry: SnapshotRetry,
+}
+
+/// Streams the `@depth@100ms` diff events and maintains the full local book. The book is synchronised
+/// with the `/api/v3/depth` snapshot following Binance's documented procedure, and resynchronised
+/// whenever the `U`/`u` continuity breaks
+#[derive(Debug, Clone)]
+pub struct BinanceDiffConnector {
+    instruments: Vec<Instrument>,
+    ws_url: String,
+    rest_url: String,
+    client: reqwest::Client,
+    /// Keyed by the Binance symbol, e.g. `btcusdt`
+    books: HashMap<String, BinanceDiffBook>,
+}
+
+impl BinanceDiffConnector {
+    pub fn new(instruments: Vec<Instrument>) -> Self {
+        Self {
+            instruments,
+            ws_url: BINANCE_WS_URL.to_string(),
+            rest_url: BINANCE_REST_URL.to_string(),
+            client: reqwest::Client::new(),
+            books: HashMap::new(),
+        }
+    }
+
+    /// Overrides the websocket base url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    /// Overrides the REST base url the snapshot is fetched from, e.g. to test against a local stand-in
+    pub fn with_rest_url(mut self, rest_url: impl Into<String>) -> Self {
+        self.rest_url = rest_url.into();
+        self
+    }
+
+    async fn fetch_snapshot(&self, instrument: &Instrument) -> Result<BinanceOrderBook, reqwest::Error> {
+        self.client
+            .get(format!("{}/api/v3/depth", self.rest_url))
+            .timeout(SNAPSHOT_TIMEOUT)
+            .query(&[
+                ("symbol", instrument.get_symbol_compact().to_uppercase()),
+                ("limit", "1000".to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Fresh local book out of a snapshot. The snapshot has to include the update
+    /// preceding `first_update_id`, otherwise it's discarded and fetched again once the retry is due
+    async fn sync(&self, instrument: &Instrument, first_update_id: usize) -> Option<BinanceDiffBook> {
+        let snapshot = match self.fetch_snapshot(instrument).await {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                error!("Couldn't fetch Binance depth snapshot. Error: {:?}", err);
+                return None;
+            }
+        };
+        if snapshot.last_update_id + 1 < first_update_id {
+            warn!(
+                "Binance snapshot {} is older than the first buffered update {}, fetching it again",
+                snapshot.last_update_id, first_update_id
+            );
+            return None;
+        }
+        info!(
+            "Synchronised Binance {} local book at update {}",
+            instrument.get_symbol_compact(),
+            snapshot.last_update_id
+        );
+        Some(BinanceDiffBook {
+            book: Some(LocalBook::from_snapshot(&snapshot.bids, &snapshot.asks)),
+            last_update_id: snapshot.last_update_id,
+            retry: SnapshotRetry::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceDiffConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Binance
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        self.instruments.clone()
+    }
+
+    fn endpoint(&self) -> String {
+        combined_endpoint(&self.ws_url, &self.instruments, "depth@100ms")
+    }
+
+    fn reset(&mut self) {
+        self.books.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let message = serde_json::from_str::<BinanceStreamMessage<BinanceDepthUpdate>>(msg)
+            .map_err(|err| StreamerError::parse(Exchange::Binance, err, msg))?;
+        let Some(instrument) = find_instrument(&self.instruments, &message.stream).cloned() else {
+            warn!("Received Binance message for a non-subscribed stream {}", message.stream);
+            return Ok(None);
+        };
+        let symbol = instrument.get_symbol_compact();
+        let update = message.data;
+
+        let state = self.books.entry(symbol.clone()).or_default();
+        if state.book.is_some() && update.first_update_id > state.last_update_id + 1 {
+            warn!(
+                "Gap in Binance {} diff stream, expected update {} got {}. Resynchronising",
+                symbol,
+                state.last_update_id + 1,
+                update.first_update_id
+            );
+            state.book = None;
+        }
+        if state.book.is_none() {
+            if !state.retry.due() {
+                return Ok(None);
+            }
+            let Some(synced) = self.sync(&instrument, update.first_update_id).await else {
+                if let Some(state) = self.books.get_mut(&symbol) {
+                    state.retry.failed();
+                }
+                return Ok(None);
+            };
+            self.books.insert(symbol.clone(), synced);
+        }
+        let Some(state) = self.books.get_mut(&symbol) else {
+            return Ok(None);
+        };
+        // Already part of the snapshot
+        if update.final_update_id <= state.last_update_id {
+            return Ok(None);
+        }
+
+        let Some(book) = state.book.as_mut() else {
+            return Ok(None);
+        };
+        book.update_bids(&update.bids);
+        book.update_asks(&update.asks);
+        state.last_update_id = update.final_update_id;
+        Ok(Some(OrderBook {
+            exchange: Exchange::Binance,
+            sequence: update.final_update_id,
+            exchange_ts: DateTime::from_timestamp_millis(update.event_time),
+            received_ts: Utc::now(),
+            instrument: instrument.get_symbol_compact(),
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        }))
+    }
+}
+
+/// Streams the `@trade` channel, every execution as it happens. Publishes [StreamEvent::Trade], no books
+#[derive(Debug, Clone)]
+pub struct BinanceTradeConnector {
+    instruments: Vec<Instrument>,
+    ws_url: String,
+    events: Vec<StreamEvent>,
+}
+
+impl BinanceTradeConnector {
+    pub fn new(instruments: Vec<Instrument>) -> Self {
+        Self {
+            instruments,
+            ws_url: BINANCE_WS_URL.to_string(),
+            events: vec![],
+        }
+    }
+
+    /// Overrides the websocket base url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceTradeConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Binance
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        self.instruments.clone()
+    }
+
+    fn kind(&self) -> ConnectionKind {
+        ConnectionKind::Trades
+    }
+
+    fn endpoint(&self) -> String {
+        combined_endpoint(&self.ws_url, &self.instruments, "trade")
+    }
+
+    fn reset(&mut self) {
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let message = serde_json::from_str::<BinanceStreamMessage<BinanceTrade>>(msg)
+            .map_err(|err| StreamerError::parse(Exchange::Binance, err, msg))?;
+        let Some(instrument) = find_instrument(&self.instruments, &message.stream) else {
+            warn!("Received Binance message for a non-subscribed stream {}", message.stream);
+            return Ok(None);
+        };
+        self.events.push(StreamEvent::Trade(Trade::from((
+            Exchange::Binance,
+            instrument.get_symbol_compact(),
+            message.data,
+        ))));
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchanges::stub::StubRest;
+    use serde_json::json;
+    use std::time::Duration;
+
+    /// Request line length most servers and proxies accept, e.g. 4KB is a common default
+    const MAX_URL_LENGTH: usize = 4096;
+
+    #[test]
+    fn full_shard_url_is_within_the_request_line_limits() {
+        // As long as the longest listed symbols, e.g. `1000cheemsusdt`
+        let shard: Vec<Instrument> = (0..BINANCE_MAX_STREAMS)
+            .map(|index| Instrument {
+                base: format!("1000cheem{}", index % 10),
+                quote: "usdt".to_string(),
+            })
+            .collect();
+        let endpoints = [
+            BinanceConnector::new(shard.clone()).endpoint(),
+            BinanceDiffConnector::new(shard.clone()).endpoint(),
+            BinanceTradeConnector::new(shard).endpoint(),
+        ];
+        for endpoint in endpoints {
+            let streams = endpoint.split_once("streams=").unwrap().1;
+            assert_eq!(streams.split('/').count(), BINANCE_MAX_STREAMS);
+            assert!(endpoint.len() < MAX_URL_LENGTH, "{} bytes long", endpoint.len());
+        }
+    }
+
+    fn snapshot(last_update_id: usize) -> String {
+        json!({
+            "lastUpdateId": last_update_id,
+            "bids": [["100.00", "1.0"], ["99.00", "2.0"]],
+            "asks": [["101.00", "1.0"], ["102.00", "2.0"]]
+        })
+        .to_string()
+    }
+
+    fn diff(first_update_id: usize, final_update_id: usize, bids: &[(&str, &str)]) -> String {
+        json!({
+            "stream": "btcusdt@depth@100ms",
+            "data": {
+                "e": "depthUpdate",
+                "E": 1700000000000u64,
+                "s": "BTCUSDT",
+                "U": first_update_id,
+                "u": final_update_id,
+                "b": bids,
+                "a": []
+            }
+        })
+        .to_string()
+    }
+
+    async fn connector(snapshots: &[String]) -> (BinanceDiffConnector, StubRest) {
+        let rest = StubRest::start(snapshots).await;
+        let connector = BinanceDiffConnector::new(vec![Instrument {
+            base: "btc".to_string(),
+            quote: "usdt".to_string(),
+        }])
+        .with_rest_url(rest.url());
+        (connector, rest)
+    }
+
+    #[tokio::test]
+    async fn events_within_the_snapshot_are_dropped_and_the_next_ones_applied() {
+        let (mut connector, rest) = connector(&[snapshot(100)]).await;
+        // Straddles the snapshot, all of it is already part of it
+        assert!(connector.parse(&diff(95, 100, &[("100.00", "5.0")])).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 1);
+
+        let book = connector
+            .parse(&diff(101, 102, &[("100.00", "0"), ("99.50", "3.0")]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(book.sequence, 102);
+        let bids: Vec<String> = book.bids.iter().map(|level| format!("{}:{}", level.price, level.amount)).collect();
+        assert_eq!(bids, ["99.50:3.0", "99.00:2.0"]);
+        assert_eq!(rest.requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn first_event_partly_within_the_snapshot_is_applied() {
+        let (mut connector, _rest) = connector(&[snapshot(100)]).await;
+        let book = connector.parse(&diff(98, 103, &[("99.00", "4.0")])).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 103);
+        assert_eq!(book.bids[1].amount.to_string(), "4.0");
+    }
+
+    #[tokio::test]
+    async fn gap_resynchronises() {
+        let (mut connector, rest) = connector(&[snapshot(100), snapshot(120)]).await;
+        assert!(connector.parse(&diff(101, 105, &[])).await.unwrap().is_some());
+
+        // 106 to 109 got lost
+        let book = connector.parse(&diff(110, 125, &[("98.00", "1.0")])).await.unwrap().unwrap();
+        assert_eq!(rest.requests(), 2);
+        assert_eq!(book.sequence, 125);
+        assert_eq!(book.bids.len(), 3);
+        assert!(connector.parse(&diff(126, 127, &[])).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn unusable_snapshot_is_retried_with_backoff() {
+        // Older than the buffered events until the third request
+        let (mut connector, rest) = connector(&[snapshot(50), snapshot(50), snapshot(300)]).await;
+        assert!(connector.parse(&diff(200, 201, &[])).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 1);
+
+        // The events keep coming every 100ms, the snapshot isn't requested for each of them
+        for update_id in 202..206 {
+            assert!(connector.parse(&diff(update_id, update_id, &[])).await.unwrap().is_none());
+        }
+        assert_eq!(rest.requests(), 1);
+
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert!(connector.parse(&diff(206, 206, &[])).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 2);
+        // The delay grows
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert!(connector.parse(&diff(207, 207, &[])).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 2);
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert!(connector.parse(&diff(299, 301, &[])).await.unwrap().is_some());
+        assert_eq!(rest.requests(), 3);
+    }
+}
@@ -1,10 +1,19 @@
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
 use crate::{
-    model::{OrderBook, OrderBookLevel},
+    catalogue::InstrumentInfo,
+    model::{Decimal, OrderBook, OrderBookLevel, Side, Trade},
     Exchange,
 };
 
+/// Envelope of the combined streams endpoint, `stream` is e.g. `btcusdt@depth20@100ms`
+#[derive(Debug, Deserialize)]
+pub struct BinanceStreamMessage<T> {
+    pub stream: String,
+    pub data: T,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BinanceOrderBook {
@@ -18,9 +27,111 @@ impl From<(Exchange, String, BinanceOrderBook)> for OrderBook {
         Self {
             exchange,
             sequence: book.last_update_id,
+            // The partial book stream doesn't carry an event time
+            exchange_ts: None,
+            received_ts: Utc::now(),
             instrument,
             bids: book.bids,
             asks: book.asks,
+            substitutions: vec![],
+        }
+    }
+}
+
+/// Event of the `<symbol>@depth@100ms` diff stream. Levels carry the new absolute amount
+#[derive(Debug, Deserialize)]
+pub struct BinanceDepthUpdate {
+    /// Event time in milliseconds
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    #[serde(rename = "U")]
+    pub first_update_id: usize,
+    #[serde(rename = "u")]
+    pub final_update_id: usize,
+    #[serde(rename = "b")]
+    pub bids: Vec<OrderBookLevel>,
+    #[serde(rename = "a")]
+    pub asks: Vec<OrderBookLevel>,
+}
+
+/// Event of the `<symbol>@trade` stream
+#[derive(Debug, Deserialize)]
+pub struct BinanceTrade {
+    #[serde(rename = "t")]
+    pub trade_id: u64,
+    #[serde(rename = "p")]
+    pub price: Decimal,
+    #[serde(rename = "q")]
+    pub quantity: Decimal,
+    /// Trade time in milliseconds
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+    /// Whether the buyer was the maker, i.e. the taker sold
+    #[serde(rename = "m")]
+    pub buyer_is_maker: bool,
+}
+
+impl From<(Exchange, String, BinanceTrade)> for Trade {
+    fn from((exchange, instrument, trade): (Exchange, String, BinanceTrade)) -> Self {
+        Self {
+            exchange,
+            instrument,
+            price: trade.price,
+            amount: trade.quantity,
+            side: if trade.buyer_is_maker { Side::Sell } else { Side::Buy },
+            trade_id: trade.trade_id.to_string(),
+            exchange_ts: DateTime::from_timestamp_millis(trade.trade_time),
+            received_ts: Utc::now(),
+            substitutions: vec![],
+        }
+    }
+}
+
+/// `/api/v3/exchangeInfo` response, only the symbols are kept
+#[derive(Debug, Deserialize)]
+pub struct BinanceExchangeInfo {
+    pub symbols: Vec<BinanceSymbol>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinanceSymbol {
+    pub symbol: String,
+    pub status: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub filters: Vec<BinanceFilter>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "filterType")]
+pub enum BinanceFilter {
+    #[serde(rename = "PRICE_FILTER", rename_all = "camelCase")]
+    Price { tick_size: String },
+    #[serde(rename = "LOT_SIZE", rename_all = "camelCase")]
+    LotSize { step_size: String },
+    #[serde(other)]
+    Other,
+}
+
+impl From<BinanceSymbol> for InstrumentInfo {
+    fn from(symbol: BinanceSymbol) -> Self {
+        let mut info = Self {
+            trading: symbol.status == "TRADING",
+            symbol: symbol.symbol,
+            base: symbol.base_asset.to_lowercase(),
+            quote: symbol.quote_asset.to_lowercase(),
+            tick_size: None,
+            lot_size: None,
+            status: symbol.status,
+        };
+        for filter in symbol.filters {
+            match filter {
+                BinanceFilter::Price { tick_size } => info.tick_size = tick_size.parse().ok(),
+                BinanceFilter::LotSize { step_size } => info.lot_size = step_size.parse().ok(),
+                BinanceFilter::Other => {}
+            }
         }
+        info
     }
 }
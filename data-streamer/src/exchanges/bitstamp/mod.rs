@@ -1 +1,425 @@
 pub mod model;
+
+use crate::{
+    book::LocalBook,
+    catalogue::InstrumentInfo,
+    connector::{ConnectionKind, ExchangeConnector, StreamEvent},
+    model::{OrderBook, Trade},
+    reconnect::{SnapshotRetry, SNAPSHOT_TIMEOUT},
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::*;
+use model::{BitStampOrderBook, BitStampOrderBookWrapper, BitstampPairInfo, BitstampTradeWrapper};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub const BITSTAMP_REST_URL: &str = "https://www.bitstamp.net";
+pub const BITSTAMP_WS_URL: &str = "wss://ws.bitstamp.net";
+
+/// Channels subscribed over a single connection, the instruments are sharded across connections beyond it.
+/// Bitstamp doesn't document a limit, this keeps a connection's message rate reasonable
+pub const BITSTAMP_MAX_CHANNELS: usize = 50;
+
+/// Instruments listed in `/api/v2/trading-pairs-info/`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let pairs: Vec<BitstampPairInfo> = client
+        .get(format!("{}/api/v2/trading-pairs-info/", rest_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(pairs.into_iter().map(Into::into).collect())
+}
+
+fn subscribe_message(channel: String) -> String {
+    json!({
+        "event": "bts:subscribe",
+        "data": {
+            "channel": channel
+        }
+    })
+    .to_string()
+}
+
+/// Instrument a channel, e.g. `order_book_btcusd`, belongs to
+fn find_instrument<'a>(instruments: &'a [Instrument], channel: &str) -> Option<&'a Instrument> {
+    let symbol = channel.rsplit('_').next().unwrap_or_default();
+    instruments
+        .iter()
+        .find(|instrument| instrument.get_symbol_compact() == symbol)
+}
+
+/// Orderbook or trade payload out of a Bitstamp message. Control messages are turned into events
+fn parse_data<T: DeserializeOwned>(msg: &str, events: &mut Vec<StreamEvent>) -> Result<Option<T>, StreamerError> {
+    let resp = serde_json::from_str::<Value>(msg).map_err(|err| StreamerError::parse(Exchange::Bitstamp, err, msg))?;
+    let channel = resp.get("channel").and_then(Value::as_str).unwrap_or_default().to_string();
+    match resp.get("event").and_then(Value::as_str) {
+        Some("data" | "trade") => serde_json::from_value::<T>(resp)
+            .map(Some)
+            .map_err(|err| StreamerError::parse(Exchange::Bitstamp, err, msg)),
+        Some("bts:subscription_succeeded") => {
+            events.push(StreamEvent::Subscribed {
+                exchange: Exchange::Bitstamp,
+                channels: vec![channel],
+            });
+            Ok(None)
+        }
+        Some("bts:error") => {
+            let message = resp.pointer("/data/message").and_then(Value::as_str).unwrap_or_default();
+            events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                Exchange::Bitstamp,
+                message,
+            )));
+            Ok(None)
+        }
+        Some("bts:request_reconnect") => Err(StreamerError::ReconnectRequested {
+            exchange: Exchange::Bitstamp,
+        }),
+        event => {
+            warn!("Received non-data message from Bitstamp. Msg: {:?}", event);
+            Ok(None)
+        }
+    }
+}
+
+/// Streams the `order_book_<pair>` channel, a 100 level snapshot on every change
+#[derive(Debug, Clone)]
+pub struct BitstampConnector {
+    instruments: Vec<Instrument>,
+    ws_url: String,
+    events: Vec<StreamEvent>,
+}
+
+impl BitstampConnector {
+    pub fn new(instruments: Vec<Instrument>) -> Self {
+        Self {
+            instruments,
+            ws_url: BITSTAMP_WS_URL.to_string(),
+            events: vec![],
+        }
+    }
+
+    /// Overrides the websocket base url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BitstampConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bitstamp
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        self.instruments.clone()
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        self.instruments
+            .iter()
+            .map(|instrument| subscribe_message(format!("order_book_{}", instrument.get_symbol_compact())))
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let Some(raw_ob) = parse_data::<BitStampOrderBookWrapper>(msg, &mut self.events)? else {
+            return Ok(None);
+        };
+        let Some(instrument) = find_instrument(&self.instruments, &raw_ob.channel) else {
+            warn!("Received Bitstamp message for a non-subscribed channel {}", raw_ob.channel);
+            return Ok(None);
+        };
+        Ok(Some(OrderBook::from((Exchange::Bitstamp, instrument.get_symbol_compact(), raw_ob))))
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Streams the `diff_order_book_<pair>` channel and maintains the full local book. The book is seeded
+/// from the `/api/v2/order_book/` snapshot, diffs not newer than the book's `microtimestamp` are dropped
+#[derive(Debug, Clone)]
+pub struct BitstampDiffConnector {
+    instruments: Vec<Instrument>,
+    ws_url: String,
+    rest_url: String,
+    client: reqwest::Client,
+    /// Local book and its `microtimestamp`, keyed by the pair, e.g. `btcusd`
+    books: HashMap<String, (LocalBook, usize)>,
+    /// Paces the snapshot requests of the pairs whose book isn't synchronised
+    retries: HashMap<String, SnapshotRetry>,
+    events: Vec<StreamEvent>,
+}
+
+impl BitstampDiffConnector {
+    pub fn new(instruments: Vec<Instrument>) -> Self {
+        Self {
+            instruments,
+            ws_url: BITSTAMP_WS_URL.to_string(),
+            rest_url: BITSTAMP_REST_URL.to_string(),
+            client: reqwest::Client::new(),
+            books: HashMap::new(),
+            retries: HashMap::new(),
+            events: vec![],
+        }
+    }
+
+    /// Overrides the websocket base url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    /// Overrides the REST base url the snapshot is fetched from, e.g. to test against a local stand-in
+    pub fn with_rest_url(mut self, rest_url: impl Into<String>) -> Self {
+        self.rest_url = rest_url.into();
+        self
+    }
+
+    async fn fetch_snapshot(&self, instrument: &Instrument) -> Result<BitStampOrderBook, reqwest::Error> {
+        self.client
+            .get(format!(
+                "{}/api/v2/order_book/{}/",
+                self.rest_url,
+                instrument.get_symbol_compact()
+            ))
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn sync(&mut self, instrument: &Instrument) -> Option<()> {
+        let snapshot = match self.fetch_snapshot(instrument).await {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                error!("Couldn't fetch Bitstamp orderbook snapshot. Error: {:?}", err);
+                return None;
+            }
+        };
+        info!(
+            "Synchronised Bitstamp {} local book at {}",
+            instrument.get_symbol_compact(),
+            snapshot.microtimestamp
+        );
+        self.books.insert(
+            instrument.get_symbol_compact(),
+            (LocalBook::from_snapshot(&snapshot.bids, &snapshot.asks), snapshot.microtimestamp),
+        );
+        Some(())
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BitstampDiffConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bitstamp
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        self.instruments.clone()
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        self.instruments
+            .iter()
+            .map(|instrument| subscribe_message(format!("diff_order_book_{}", instrument.get_symbol_compact())))
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        self.books.clear();
+        self.retries.clear();
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let Some(diff) = parse_data::<BitStampOrderBookWrapper>(msg, &mut self.events)? else {
+            return Ok(None);
+        };
+        let Some(instrument) = find_instrument(&self.instruments, &diff.channel).cloned() else {
+            warn!("Received Bitstamp message for a non-subscribed channel {}", diff.channel);
+            return Ok(None);
+        };
+        let symbol = instrument.get_symbol_compact();
+        let diff = diff.data;
+        if !self.books.contains_key(&symbol) {
+            let retry = self.retries.entry(symbol.clone()).or_default();
+            if !retry.due() {
+                return Ok(None);
+            }
+            if self.sync(&instrument).await.is_none() {
+                self.retries.entry(symbol).or_default().failed();
+                return Ok(None);
+            }
+            self.retries.remove(&symbol);
+        }
+
+        let Some((book, microtimestamp)) = self.books.get_mut(&symbol) else {
+            return Ok(None);
+        };
+        // Already part of the book
+        if diff.microtimestamp <= *microtimestamp {
+            return Ok(None);
+        }
+        book.update_bids(&diff.bids);
+        book.update_asks(&diff.asks);
+        *microtimestamp = diff.microtimestamp;
+        Ok(Some(OrderBook {
+            exchange: Exchange::Bitstamp,
+            sequence: diff.microtimestamp,
+            exchange_ts: DateTime::from_timestamp_micros(diff.microtimestamp as i64),
+            received_ts: Utc::now(),
+            instrument: symbol,
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        }))
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Streams the `live_trades_<pair>` channel, every execution as it happens. Publishes [StreamEvent::Trade], no books
+#[derive(Debug, Clone)]
+pub struct BitstampTradeConnector {
+    instruments: Vec<Instrument>,
+    ws_url: String,
+    events: Vec<StreamEvent>,
+}
+
+impl BitstampTradeConnector {
+    pub fn new(instruments: Vec<Instrument>) -> Self {
+        Self {
+            instruments,
+            ws_url: BITSTAMP_WS_URL.to_string(),
+            events: vec![],
+        }
+    }
+
+    /// Overrides the websocket base url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BitstampTradeConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bitstamp
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        self.instruments.clone()
+    }
+
+    fn kind(&self) -> ConnectionKind {
+        ConnectionKind::Trades
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        self.instruments
+            .iter()
+            .map(|instrument| subscribe_message(format!("live_trades_{}", instrument.get_symbol_compact())))
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let Some(trade) = parse_data::<BitstampTradeWrapper>(msg, &mut self.events)? else {
+            return Ok(None);
+        };
+        let Some(instrument) = find_instrument(&self.instruments, &trade.channel) else {
+            warn!("Received Bitstamp message for a non-subscribed channel {}", trade.channel);
+            return Ok(None);
+        };
+        self.events.push(StreamEvent::Trade(Trade::from((
+            Exchange::Bitstamp,
+            instrument.get_symbol_compact(),
+            trade,
+        ))));
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchanges::stub::StubRest;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn diff(microtimestamp: usize) -> String {
+        json!({
+            "event": "data",
+            "channel": "diff_order_book_btcusd",
+            "data": {
+                "timestamp": (microtimestamp / 1_000_000).to_string(),
+                "microtimestamp": microtimestamp.to_string(),
+                "bids": [["99.00", "2.0"]],
+                "asks": []
+            }
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn failed_snapshot_is_retried_with_backoff() {
+        let snapshot = json!({
+            "timestamp": "1700000000",
+            "microtimestamp": "1700000000000000",
+            "bids": [["100.00", "1.0"]],
+            "asks": [["101.00", "1.0"]]
+        })
+        .to_string();
+        let rest = StubRest::start(&["{}".to_string(), snapshot]).await;
+        let mut connector = BitstampDiffConnector::new(vec![Instrument {
+            base: "btc".to_string(),
+            quote: "usd".to_string(),
+        }])
+        .with_rest_url(rest.url());
+        assert!(connector.parse(&diff(1_700_000_000_100_000)).await.unwrap().is_none());
+        assert!(connector.parse(&diff(1_700_000_000_200_000)).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 1);
+
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let book = connector.parse(&diff(1_700_000_000_300_000)).await.unwrap().unwrap();
+        assert_eq!(rest.requests(), 2);
+        assert_eq!(book.bids.len(), 2);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
-    model::{de_usize_from_str, OrderBook, OrderBookLevel},
+    catalogue::{decimals_to_size, InstrumentInfo},
+    model::{de_usize_from_str, Decimal, OrderBook, OrderBookLevel, Side, Trade},
     Exchange,
 };
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -28,9 +30,77 @@ impl From<(Exchange, String, BitStampOrderBookWrapper)> for OrderBook {
         Self {
             exchange,
             sequence: book.data.timestamp,
+            exchange_ts: DateTime::from_timestamp_micros(book.data.microtimestamp as i64),
+            received_ts: Utc::now(),
             instrument,
             bids: book.data.bids,
             asks: book.data.asks,
+            substitutions: vec![],
+        }
+    }
+}
+
+/// Payload of the `live_trades_<pair>` channel
+#[derive(Debug, Deserialize)]
+pub struct BitstampTrade {
+    pub id: u64,
+    /// Exact amount, `amount` is a JSON number
+    pub amount_str: Decimal,
+    /// Exact price, `price` is a JSON number
+    pub price_str: Decimal,
+    /// `0` for a buy, `1` for a sell
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(deserialize_with = "de_usize_from_str")]
+    pub microtimestamp: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BitstampTradeWrapper {
+    pub event: String,
+    pub channel: String,
+    pub data: BitstampTrade,
+}
+
+impl From<(Exchange, String, BitstampTradeWrapper)> for Trade {
+    fn from((exchange, instrument, trade): (Exchange, String, BitstampTradeWrapper)) -> Self {
+        Self {
+            exchange,
+            instrument,
+            price: trade.data.price_str,
+            amount: trade.data.amount_str,
+            side: if trade.data.kind == 0 { Side::Buy } else { Side::Sell },
+            trade_id: trade.data.id.to_string(),
+            exchange_ts: DateTime::from_timestamp_micros(trade.data.microtimestamp as i64),
+            received_ts: Utc::now(),
+            substitutions: vec![],
+        }
+    }
+}
+
+/// Entry of the `/api/v2/trading-pairs-info/` response
+#[derive(Debug, Deserialize)]
+pub struct BitstampPairInfo {
+    /// e.g. `BTC/USD`
+    pub name: String,
+    pub url_symbol: String,
+    pub base_decimals: u32,
+    pub counter_decimals: u32,
+    /// `Enabled` or `Disabled`
+    pub trading: String,
+}
+
+impl From<BitstampPairInfo> for InstrumentInfo {
+    fn from(pair: BitstampPairInfo) -> Self {
+        let (base, quote) = pair.name.split_once('/').unwrap_or_default();
+        Self {
+            base: base.to_lowercase(),
+            quote: quote.to_lowercase(),
+            symbol: pair.url_symbol,
+            tick_size: Some(decimals_to_size(pair.counter_decimals)),
+            lot_size: Some(decimals_to_size(pair.base_decimals)),
+            trading: pair.trading == "Enabled",
+            status: pair.trading,
         }
     }
 }
@@ -0,0 +1,335 @@
+pub mod model;
+
+use crate::{
+    book::LocalBook,
+    catalogue::InstrumentInfo,
+    connector::{ExchangeConnector, StreamEvent, Heartbeat},
+    model::OrderBook,
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::*;
+use model::{BybitBookMessage, BybitInstruments, BybitOpResponse};
+use serde_json::{json, Value};
+use std::{str::FromStr, time::Duration};
+
+pub const BYBIT_REST_URL: &str = "https://api.bybit.com";
+/// The category is appended to it, e.g. `/spot`
+pub const BYBIT_WS_URL: &str = "wss://stream.bybit.com/v5/public";
+
+/// Instruments of the category listed in `/v5/market/instruments-info`. Linear futures are limited to the
+/// perpetuals, the ones named after their assets like the connector subscribes to them
+pub async fn fetch_instruments(
+    client: &reqwest::Client,
+    rest_url: &str,
+    category: BybitCategory,
+) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let mut listed = vec![];
+    let mut cursor = String::new();
+    // Linear futures are paginated, spot comes in a single page
+    loop {
+        let mut query = vec![("category", category.name())];
+        if category != BybitCategory::Spot {
+            query.extend([("limit", "1000"), ("cursor", cursor.as_str())]);
+        }
+        let instruments: BybitInstruments = client
+            .get(format!("{}/v5/market/instruments-info", rest_url))
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        listed.extend(instruments.result.list);
+        match instruments.result.next_page_cursor.filter(|next| !next.is_empty()) {
+            Some(next) => cursor = next,
+            None => break,
+        }
+    }
+    Ok(listed
+        .into_iter()
+        .filter(|instrument| {
+            category == BybitCategory::Spot
+                || instrument.symbol == format!("{}{}", instrument.base_coin, instrument.quote_coin)
+        })
+        .map(Into::into)
+        .collect())
+}
+
+/// Market a [BybitConnector] streams and whose instruments are validated
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BybitCategory {
+    #[default]
+    Spot,
+    /// USDT perpetuals
+    Linear,
+}
+
+impl FromStr for BybitCategory {
+    type Err = String;
+
+    /// `spot` or `linear`
+    fn from_str(category: &str) -> Result<Self, Self::Err> {
+        match category {
+            "spot" => Ok(BybitCategory::Spot),
+            "linear" => Ok(BybitCategory::Linear),
+            category => Err(format!("invalid Bybit category {}, expected spot or linear", category)),
+        }
+    }
+}
+
+impl BybitCategory {
+    /// As in the websocket path and the `category` query parameter
+    pub fn name(&self) -> &'static str {
+        match self {
+            BybitCategory::Spot => "spot",
+            BybitCategory::Linear => "linear",
+        }
+    }
+}
+
+/// Streams `orderbook.{depth}.{symbol}` from the v5 public websocket and maintains the book at that depth.
+/// Bybit drops connections that don't send `{"op":"ping"}` every 20s
+#[derive(Debug, Clone)]
+pub struct BybitConnector {
+    instrument: Instrument,
+    category: BybitCategory,
+    depth: usize,
+    ws_url: String,
+    book: Option<LocalBook>,
+    events: Vec<StreamEvent>,
+}
+
+impl BybitConnector {
+    pub fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            category: BybitCategory::default(),
+            depth: 50,
+            ws_url: BYBIT_WS_URL.to_string(),
+            book: None,
+            events: vec![],
+        }
+    }
+
+    pub fn with_category(mut self, category: BybitCategory) -> Self {
+        self.category = category;
+        self
+    }
+
+    /// Book depth to subscribe to. Spot accepts 1, 50 and 200, linear also 500
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Overrides the websocket url the category is appended to, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    fn topic(&self) -> String {
+        format!(
+            "orderbook.{}.{}",
+            self.depth,
+            self.instrument.get_symbol_compact().to_uppercase()
+        )
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BybitConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bybit
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![self.instrument.clone()]
+    }
+
+    fn endpoint(&self) -> String {
+        format!("{}/{}", self.ws_url, self.category.name())
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![json!({ "op": "subscribe", "args": [self.topic()] }).to_string()]
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Message {
+            interval: Duration::from_secs(20),
+            message: json!({ "op": "ping" }).to_string(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.book = None;
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let parse_error = |err: serde_json::Error| StreamerError::parse(Exchange::Bybit, err, msg);
+        let resp = serde_json::from_str::<Value>(msg).map_err(parse_error)?;
+        if resp.get("op").is_some() {
+            let resp = serde_json::from_value::<BybitOpResponse>(resp).map_err(parse_error)?;
+            match (resp.op.as_str(), resp.success) {
+                ("subscribe", Some(true)) => self.events.push(StreamEvent::Subscribed {
+                    exchange: Exchange::Bybit,
+                    channels: vec![self.topic()],
+                }),
+                ("subscribe", _) => self.events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                    Exchange::Bybit,
+                    resp.ret_msg.unwrap_or_default(),
+                ))),
+                // Answers to our heartbeat
+                _ => {}
+            }
+            return Ok(None);
+        }
+        let book_msg = serde_json::from_value::<BybitBookMessage>(resp).map_err(parse_error)?;
+        let data = book_msg.data;
+        // `u == 1` is a snapshot sent after a service restart, whatever the type says
+        let is_snapshot = book_msg.kind == "snapshot" || data.update_id == 1;
+        match (is_snapshot, book_msg.kind.as_str(), self.book.as_mut()) {
+            (true, _, _) => {
+                self.book = Some(LocalBook::from_snapshot(&data.bids, &data.asks));
+            }
+            (false, "delta", Some(book)) => {
+                book.update_bids(&data.bids);
+                book.update_asks(&data.asks);
+            }
+            (false, "delta", None) => {
+                warn!("Received Bybit delta for {} before the snapshot", book_msg.topic);
+                return Ok(None);
+            }
+            (false, kind, _) => {
+                warn!("Received non-handled book message from Bybit. Type: {}", kind);
+                return Ok(None);
+            }
+        }
+        let Some(book) = self.book.as_mut() else {
+            return Ok(None);
+        };
+        book.truncate(self.depth);
+        Ok(Some(OrderBook {
+            exchange: Exchange::Bybit,
+            sequence: data.update_id,
+            exchange_ts: book_msg.ts.and_then(DateTime::from_timestamp_millis),
+            received_ts: Utc::now(),
+            instrument: self.instrument.get_symbol_compact(),
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        }))
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::OrderBookLevel;
+
+    fn connector() -> BybitConnector {
+        BybitConnector::new(Instrument {
+            base: "btc".to_string(),
+            quote: "usdt".to_string(),
+        })
+    }
+
+    fn message(kind: &str, update_id: usize, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> String {
+        json!({
+            "topic": "orderbook.50.BTCUSDT",
+            "type": kind,
+            "ts": 1700000000000i64,
+            "data": { "s": "BTCUSDT", "b": bids, "a": asks, "u": update_id, "seq": 1000 + update_id }
+        })
+        .to_string()
+    }
+
+    fn levels(levels: &[OrderBookLevel]) -> Vec<String> {
+        levels
+            .iter()
+            .map(|level| format!("{}:{}", level.price, level.amount))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn deltas_are_applied_to_the_snapshot() {
+        let mut connector = connector();
+        let snapshot = message("snapshot", 10, &[("100.0", "1"), ("99.0", "2")], &[("101.0", "1"), ("102.0", "2")]);
+        let book = connector.parse(&snapshot).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 10);
+        assert_eq!(levels(&book.bids), ["100.0:1", "99.0:2"]);
+
+        let delta = message("delta", 11, &[("100.0", "0"), ("99.5", "3")], &[("101.0", "4")]);
+        let book = connector.parse(&delta).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 11);
+        assert_eq!(book.instrument, "btcusdt");
+        assert!(book.exchange_ts.is_some());
+        assert_eq!(levels(&book.bids), ["99.5:3", "99.0:2"]);
+        assert_eq!(levels(&book.asks), ["101.0:4", "102.0:2"]);
+    }
+
+    #[tokio::test]
+    async fn deltas_before_the_snapshot_are_ignored() {
+        let mut connector = connector();
+        let delta = message("delta", 11, &[("100.0", "1")], &[]);
+        assert!(connector.parse(&delta).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn first_update_id_replaces_the_book() {
+        let mut connector = connector();
+        let snapshot = message("snapshot", 10, &[("100.0", "1"), ("99.0", "2")], &[("101.0", "1")]);
+        connector.parse(&snapshot).await.unwrap();
+        // Sent as a delta after a service restart, it's a full snapshot nonetheless
+        let restart = message("delta", 1, &[("98.0", "5")], &[("103.0", "5")]);
+        let book = connector.parse(&restart).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 1);
+        assert_eq!(levels(&book.bids), ["98.0:5"]);
+        assert_eq!(levels(&book.asks), ["103.0:5"]);
+    }
+
+    #[tokio::test]
+    async fn book_is_truncated_to_the_depth() {
+        let mut connector = connector().with_depth(1);
+        let snapshot = message("snapshot", 10, &[("100.0", "1")], &[("101.0", "1")]);
+        connector.parse(&snapshot).await.unwrap();
+        let delta = message("delta", 11, &[("100.5", "1")], &[("102.0", "1")]);
+        let book = connector.parse(&delta).await.unwrap().unwrap();
+        assert_eq!(levels(&book.bids), ["100.5:1"]);
+        assert_eq!(levels(&book.asks), ["101.0:1"]);
+    }
+
+    #[tokio::test]
+    async fn subscribe_responses_are_reported() {
+        let mut connector = connector();
+        let subscribed = json!({ "success": true, "ret_msg": "", "conn_id": "1", "op": "subscribe" });
+        assert!(connector.parse(&subscribed.to_string()).await.unwrap().is_none());
+        let events = connector.events();
+        let [StreamEvent::Subscribed { exchange, channels }] = &events[..] else {
+            panic!("Expected a subscription, got {:?}", events);
+        };
+        assert_eq!(*exchange, Exchange::Bybit);
+        assert_eq!(channels, &["orderbook.50.BTCUSDT"]);
+
+        let rejected = json!({ "success": false, "ret_msg": "error:handler not found", "op": "subscribe" });
+        assert!(connector.parse(&rejected.to_string()).await.unwrap().is_none());
+        let events = connector.events();
+        let [StreamEvent::Error(err)] = &events[..] else {
+            panic!("Expected an error, got {:?}", events);
+        };
+        assert_eq!(*err, StreamerError::subscription_rejected(Exchange::Bybit, "error:handler not found"));
+
+        // Answers to the heartbeat
+        let pong = json!({ "success": true, "ret_msg": "pong", "op": "ping" });
+        assert!(connector.parse(&pong.to_string()).await.unwrap().is_none());
+        assert!(connector.events().is_empty());
+    }
+}
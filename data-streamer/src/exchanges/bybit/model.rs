@@ -0,0 +1,92 @@
+use serde::Deserialize;
+
+use crate::{catalogue::InstrumentInfo, model::OrderBookLevel};
+
+/// Responses to `subscribe` and `ping` operations
+#[derive(Debug, Deserialize)]
+pub struct BybitOpResponse {
+    pub op: String,
+    pub success: Option<bool>,
+    pub ret_msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BybitBookMessage {
+    pub topic: String,
+    /// `snapshot` or `delta`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Milliseconds
+    pub ts: Option<i64>,
+    pub data: BybitBook,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BybitBook {
+    #[serde(rename = "b")]
+    pub bids: Vec<OrderBookLevel>,
+    #[serde(rename = "a")]
+    pub asks: Vec<OrderBookLevel>,
+    /// Update id, `1` means the service restarted and the message is a snapshot
+    #[serde(rename = "u")]
+    pub update_id: usize,
+}
+
+/// `/v5/market/instruments-info` response
+#[derive(Debug, Deserialize)]
+pub struct BybitInstruments {
+    pub result: BybitInstrumentList,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitInstrumentList {
+    pub list: Vec<BybitInstrument>,
+    /// Cursor of the next page, empty on the last one
+    pub next_page_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitInstrument {
+    pub symbol: String,
+    pub base_coin: String,
+    pub quote_coin: String,
+    /// e.g. `Trading`
+    pub status: String,
+    pub price_filter: BybitPriceFilter,
+    pub lot_size_filter: BybitLotSizeFilter,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitPriceFilter {
+    pub tick_size: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitLotSizeFilter {
+    /// Spot amount increment
+    pub base_precision: Option<String>,
+    /// Linear futures amount increment
+    pub qty_step: Option<String>,
+}
+
+impl From<BybitInstrument> for InstrumentInfo {
+    fn from(instrument: BybitInstrument) -> Self {
+        Self {
+            trading: instrument.status == "Trading",
+            symbol: instrument.symbol,
+            base: instrument.base_coin.to_lowercase(),
+            quote: instrument.quote_coin.to_lowercase(),
+            tick_size: instrument.price_filter.tick_size.parse().ok(),
+            lot_size: instrument
+                .lot_size_filter
+                .base_precision
+                .or(instrument.lot_size_filter.qty_step)
+                .and_then(|lot_size| lot_size.parse().ok()),
+            status: instrument.status,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+pub mod model;
+
+use crate::{
+    book::LocalBook,
+    catalogue::InstrumentInfo,
+    connector::{ExchangeConnector, StreamEvent},
+    model::{OrderBook, OrderBookLevel},
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::*;
+use model::{CoinbaseMessage, CoinbaseProduct, CoinbaseSide};
+use serde_json::json;
+
+pub const COINBASE_REST_URL: &str = "https://api.exchange.coinbase.com";
+pub const COINBASE_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+/// Instruments listed in `/products`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let products: Vec<CoinbaseProduct> = client
+        .get(format!("{}/products", rest_url))
+        // Coinbase rejects requests without a user agent
+        .header(reqwest::header::USER_AGENT, "data-streamer")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(products.into_iter().map(Into::into).collect())
+}
+
+/// Streams the `level2_batch` channel, a snapshot followed by `l2update` batches every 50ms,
+/// and maintains the full local book
+#[derive(Debug, Clone)]
+pub struct CoinbaseConnector {
+    instrument: Instrument,
+    ws_url: String,
+    book: Option<LocalBook>,
+    /// `level2_batch` has no update id, updates applied since the last snapshot are counted instead
+    sequence: usize,
+    events: Vec<StreamEvent>,
+}
+
+impl CoinbaseConnector {
+    pub fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            ws_url: COINBASE_WS_URL.to_string(),
+            book: None,
+            sequence: 0,
+            events: vec![],
+        }
+    }
+
+    /// Overrides the websocket url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    fn product_id(&self) -> String {
+        self.instrument.get_symbol_dash().to_uppercase()
+    }
+
+    fn to_orderbook(&self, exchange_ts: Option<DateTime<Utc>>) -> Option<OrderBook> {
+        let book = self.book.as_ref()?;
+        Some(OrderBook {
+            exchange: Exchange::Coinbase,
+            sequence: self.sequence,
+            exchange_ts,
+            received_ts: Utc::now(),
+            instrument: self.instrument.get_symbol_compact(),
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        })
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for CoinbaseConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Coinbase
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![self.instrument.clone()]
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![json!({
+            "type": "subscribe",
+            "product_ids": [self.product_id()],
+            "channels": ["level2_batch"]
+        })
+        .to_string()]
+    }
+
+    fn reset(&mut self) {
+        self.book = None;
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let message = serde_json::from_str::<CoinbaseMessage>(msg)
+            .map_err(|err| StreamerError::parse(Exchange::Coinbase, err, msg))?;
+        Ok(match message {
+            CoinbaseMessage::Snapshot(snapshot) => {
+                info!("Received Coinbase snapshot for {}", snapshot.product_id);
+                self.book = Some(LocalBook::from_snapshot(&snapshot.bids, &snapshot.asks));
+                self.sequence = 0;
+                // Snapshots don't carry a time
+                self.to_orderbook(None)
+            }
+            CoinbaseMessage::L2Update(update) => {
+                let Some(book) = self.book.as_mut() else {
+                    warn!("Received Coinbase update for {} before the snapshot", update.product_id);
+                    return Ok(None);
+                };
+                for change in update.changes {
+                    let level = [OrderBookLevel {
+                        price: change.price,
+                        amount: change.size,
+                    }];
+                    match change.side {
+                        CoinbaseSide::Buy => book.update_bids(&level),
+                        CoinbaseSide::Sell => book.update_asks(&level),
+                    }
+                }
+                self.sequence += 1;
+                self.to_orderbook(update.time)
+            }
+            CoinbaseMessage::Subscriptions(subscriptions) => {
+                self.events.push(StreamEvent::Subscribed {
+                    exchange: Exchange::Coinbase,
+                    channels: subscriptions
+                        .channels
+                        .into_iter()
+                        .flat_map(|channel| {
+                            let name = channel.name;
+                            channel
+                                .product_ids
+                                .into_iter()
+                                .map(move |product_id| format!("{}:{}", name, product_id))
+                        })
+                        .collect(),
+                });
+                None
+            }
+            CoinbaseMessage::Error(err) => {
+                self.events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                    Exchange::Coinbase,
+                    match err.reason {
+                        Some(reason) => format!("{}: {}", err.message, reason),
+                        None => err.message,
+                    },
+                )));
+                None
+            }
+            CoinbaseMessage::Other => None,
+        })
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connector() -> CoinbaseConnector {
+        CoinbaseConnector::new(Instrument {
+            base: "btc".to_string(),
+            quote: "usd".to_string(),
+        })
+    }
+
+    fn snapshot() -> String {
+        json!({
+            "type": "snapshot",
+            "product_id": "BTC-USD",
+            "bids": [["30000.00", "1.5"], ["29999.50", "2"]],
+            "asks": [["30000.50", "0.5"], ["30001.00", "3"]]
+        })
+        .to_string()
+    }
+
+    fn update(changes: &[(&str, &str, &str)]) -> String {
+        json!({
+            "type": "l2update",
+            "product_id": "BTC-USD",
+            "time": "2023-11-14T22:13:20.000000Z",
+            "changes": changes
+        })
+        .to_string()
+    }
+
+    fn levels(levels: &[OrderBookLevel]) -> Vec<String> {
+        levels
+            .iter()
+            .map(|level| format!("{}:{}", level.price, level.amount))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn updates_are_applied_to_the_snapshot() {
+        let mut connector = connector();
+        let book = connector.parse(&snapshot()).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 0);
+        assert_eq!(book.exchange_ts, None);
+        assert_eq!(levels(&book.bids), ["30000.00:1.5", "29999.50:2"]);
+
+        let book = connector
+            .parse(&update(&[
+                ("buy", "30000.00", "0"),
+                ("buy", "29999.75", "4"),
+                ("sell", "30000.50", "0.25"),
+            ]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(book.sequence, 1);
+        assert!(book.exchange_ts.is_some());
+        assert_eq!(book.instrument, "btcusd");
+        assert_eq!(levels(&book.bids), ["29999.75:4", "29999.50:2"]);
+        assert_eq!(levels(&book.asks), ["30000.50:0.25", "30001.00:3"]);
+    }
+
+    #[tokio::test]
+    async fn updates_before_the_snapshot_are_ignored() {
+        let mut connector = connector();
+        assert!(connector.parse(&update(&[("buy", "30000.00", "1")])).await.unwrap().is_none());
+        let book = connector.parse(&snapshot()).await.unwrap().unwrap();
+        assert_eq!(levels(&book.bids), ["30000.00:1.5", "29999.50:2"]);
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_reported() {
+        let mut connector = connector();
+        let msg = json!({
+            "type": "subscriptions",
+            "channels": [{ "name": "level2_batch", "product_ids": ["BTC-USD"] }]
+        });
+        assert!(connector.parse(&msg.to_string()).await.unwrap().is_none());
+        let events = connector.events();
+        let [StreamEvent::Subscribed { exchange, channels }] = &events[..] else {
+            panic!("Expected a subscription, got {:?}", events);
+        };
+        assert_eq!(*exchange, Exchange::Coinbase);
+        assert_eq!(channels, &["level2_batch:BTC-USD"]);
+    }
+
+    #[tokio::test]
+    async fn errors_are_rejected_subscriptions() {
+        let mut connector = connector();
+        let msg = json!({
+            "type": "error",
+            "message": "Failed to subscribe",
+            "reason": "BTC-XYZ is not a valid product"
+        });
+        assert!(connector.parse(&msg.to_string()).await.unwrap().is_none());
+        let events = connector.events();
+        let [StreamEvent::Error(err)] = &events[..] else {
+            panic!("Expected an error, got {:?}", events);
+        };
+        let reason = "Failed to subscribe: BTC-XYZ is not a valid product";
+        assert_eq!(*err, StreamerError::subscription_rejected(Exchange::Coinbase, reason));
+    }
+}
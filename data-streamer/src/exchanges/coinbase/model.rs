@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    catalogue::InstrumentInfo,
+    model::{Decimal, OrderBookLevel},
+};
+
+/// Messages of the Coinbase Exchange feed, tagged by `type`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoinbaseMessage {
+    Snapshot(CoinbaseSnapshot),
+    #[serde(rename = "l2update")]
+    L2Update(CoinbaseL2Update),
+    Subscriptions(CoinbaseSubscriptions),
+    Error(CoinbaseError),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseSnapshot {
+    pub product_id: String,
+    pub bids: Vec<OrderBookLevel>,
+    pub asks: Vec<OrderBookLevel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseL2Update {
+    pub product_id: String,
+    pub time: Option<DateTime<Utc>>,
+    pub changes: Vec<CoinbaseChange>,
+}
+
+/// `[side, price, size]`, the size is the new absolute amount at that price
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseChange {
+    pub side: CoinbaseSide,
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CoinbaseSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseSubscriptions {
+    pub channels: Vec<CoinbaseChannel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseChannel {
+    pub name: String,
+    pub product_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseError {
+    pub message: String,
+    pub reason: Option<String>,
+}
+
+/// Entry of the `/products` response
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseProduct {
+    /// e.g. `BTC-USD`
+    pub id: String,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub quote_increment: String,
+    pub base_increment: String,
+    /// e.g. `online`, `delisted`
+    pub status: String,
+    #[serde(default)]
+    pub trading_disabled: bool,
+}
+
+impl From<CoinbaseProduct> for InstrumentInfo {
+    fn from(product: CoinbaseProduct) -> Self {
+        Self {
+            trading: product.status == "online" && !product.trading_disabled,
+            symbol: product.id,
+            base: product.base_currency.to_lowercase(),
+            quote: product.quote_currency.to_lowercase(),
+            tick_size: product.quote_increment.parse().ok(),
+            lot_size: product.base_increment.parse().ok(),
+            status: product.status,
+        }
+    }
+}
@@ -0,0 +1,203 @@
+pub mod model;
+
+use crate::{
+    catalogue::InstrumentInfo,
+    connector::{ExchangeConnector, StreamEvent},
+    model::OrderBook,
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use log::*;
+use model::{HtxDepthMessage, HtxSubResponse, HtxSymbols};
+use serde_json::{json, Value};
+use std::io::Read;
+
+pub const HTX_REST_URL: &str = "https://api.huobi.pro";
+pub const HTX_WS_URL: &str = "wss://api.huobi.pro/ws";
+
+/// Instruments listed in `/v1/common/symbols`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let symbols: HtxSymbols = client
+        .get(format!("{}/v1/common/symbols", rest_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(symbols.data.into_iter().map(Into::into).collect())
+}
+
+/// Streams `market.<symbol>.depth.step0`, a 150 level snapshot on every change.
+/// HTX sends every message as gzip compressed binary and pings at the application level
+#[derive(Debug, Clone)]
+pub struct HtxConnector {
+    instrument: Instrument,
+    ws_url: String,
+    outgoing: Vec<String>,
+    events: Vec<StreamEvent>,
+}
+
+impl HtxConnector {
+    pub fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            ws_url: HTX_WS_URL.to_string(),
+            outgoing: vec![],
+            events: vec![],
+        }
+    }
+
+    /// Overrides the websocket url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    fn topic(&self) -> String {
+        format!("market.{}.depth.step0", self.instrument.get_symbol_compact().to_lowercase())
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for HtxConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Htx
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![self.instrument.clone()]
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![json!({ "sub": self.topic(), "id": self.topic() }).to_string()]
+    }
+
+    fn reset(&mut self) {
+        self.outgoing.clear();
+        self.events.clear();
+    }
+
+    fn decode_binary(&self, msg: &[u8]) -> Result<Option<String>, StreamerError> {
+        let mut text = String::new();
+        GzDecoder::new(msg)
+            .read_to_string(&mut text)
+            .map_err(|err| StreamerError::parse(Exchange::Htx, err, &format!("{:?}", msg)))?;
+        Ok(Some(text))
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let parse_error = |err: serde_json::Error| StreamerError::parse(Exchange::Htx, err, msg);
+        let resp = serde_json::from_str::<Value>(msg).map_err(parse_error)?;
+        // Application level heartbeat, has to be answered with the same value
+        if let Some(ping) = resp.get("ping").and_then(Value::as_u64) {
+            self.outgoing.push(json!({ "pong": ping }).to_string());
+            return Ok(None);
+        }
+        if resp.get("status").is_some() {
+            let resp = serde_json::from_value::<HtxSubResponse>(resp).map_err(parse_error)?;
+            match (resp.status.as_str(), resp.subbed) {
+                ("ok", Some(subbed)) => self.events.push(StreamEvent::Subscribed {
+                    exchange: Exchange::Htx,
+                    channels: vec![subbed],
+                }),
+                ("error", _) => self.events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                    Exchange::Htx,
+                    resp.err_msg.unwrap_or_default(),
+                ))),
+                _ => {}
+            }
+            return Ok(None);
+        }
+        let depth = serde_json::from_value::<HtxDepthMessage>(resp).map_err(parse_error)?;
+        if depth.ch != self.topic() {
+            warn!("Received HTX message for a non-subscribed channel {}", depth.ch);
+            return Ok(None);
+        }
+        Ok(Some(OrderBook {
+            exchange: Exchange::Htx,
+            sequence: depth.tick.version,
+            exchange_ts: depth.ts.and_then(DateTime::from_timestamp_millis),
+            received_ts: Utc::now(),
+            instrument: self.instrument.get_symbol_compact(),
+            bids: depth.tick.bids.into_iter().map(Into::into).collect(),
+            asks: depth.tick.asks.into_iter().map(Into::into).collect(),
+            substitutions: vec![],
+        }))
+    }
+
+    fn outgoing(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn connector() -> HtxConnector {
+        HtxConnector::new(Instrument {
+            base: "btc".to_string(),
+            quote: "usdt".to_string(),
+        })
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn gzipped_frames_are_decoded_and_pings_answered() {
+        let mut connector = connector();
+        let ping = connector.decode_binary(&gzip(r#"{"ping":1700000000000}"#)).unwrap().unwrap();
+        assert!(connector.parse(&ping).await.unwrap().is_none());
+        let outgoing: Vec<Value> = connector
+            .outgoing()
+            .iter()
+            .map(|msg| serde_json::from_str(msg).unwrap())
+            .collect();
+        assert_eq!(outgoing, [json!({ "pong": 1700000000000u64 })]);
+
+        let depth = json!({
+            "ch": "market.btcusdt.depth.step0",
+            "ts": 1700000000000i64,
+            "tick": { "version": 42, "bids": [[37000.1, 0.5]], "asks": [[37000.2, 1.25]] }
+        });
+        let depth = connector.decode_binary(&gzip(&depth.to_string())).unwrap().unwrap();
+        let book = connector.parse(&depth).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 42);
+        assert_eq!(book.instrument, "btcusdt");
+        assert_eq!(book.bids[0].price.to_string(), "37000.1");
+        assert_eq!(book.asks[0].amount.to_string(), "1.25");
+        assert!(connector.outgoing().is_empty());
+    }
+
+    #[test]
+    fn corrupt_frames_are_parse_errors() {
+        assert!(connector().decode_binary(b"not gzip").is_err());
+    }
+
+    #[tokio::test]
+    async fn depth_keeps_the_exact_decimals_of_the_json_numbers() {
+        let mut connector = connector();
+        let msg = r#"{"ch":"market.btcusdt.depth.step0","ts":1700000000000,
+            "tick":{"version":7,"bids":[[37000.1,0.000123456789012345678]],"asks":[[37000.2,12.3456789012345678]]}}"#;
+        let book = connector.parse(msg).await.unwrap().unwrap();
+        assert_eq!(book.bids[0].amount.to_string(), "0.000123456789012345678");
+        assert_eq!(book.asks[0].amount.to_string(), "12.3456789012345678");
+        assert_eq!(book.asks[0].price.to_string(), "37000.2");
+    }
+}
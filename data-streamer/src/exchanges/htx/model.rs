@@ -0,0 +1,72 @@
+use serde::Deserialize;
+
+use crate::{
+    catalogue::{decimals_to_size, InstrumentInfo},
+    model::{Decimal, OrderBookLevel},
+};
+
+/// Response to a `sub` request
+#[derive(Debug, Deserialize)]
+pub struct HtxSubResponse {
+    pub status: String,
+    pub subbed: Option<String>,
+    #[serde(rename = "err-msg")]
+    pub err_msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HtxDepthMessage {
+    pub ch: String,
+    /// Milliseconds
+    pub ts: Option<i64>,
+    pub tick: HtxDepth,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HtxDepth {
+    pub version: usize,
+    pub bids: Vec<HtxLevel>,
+    pub asks: Vec<HtxLevel>,
+}
+
+/// `[price, amount]` as JSON numbers, read from their text as serde_json's `arbitrary_precision` keeps it
+#[derive(Debug, Deserialize, Clone)]
+pub struct HtxLevel(pub Decimal, pub Decimal);
+
+impl From<HtxLevel> for OrderBookLevel {
+    fn from(HtxLevel(price, amount): HtxLevel) -> Self {
+        Self { price, amount }
+    }
+}
+
+/// `/v1/common/symbols` response
+#[derive(Debug, Deserialize)]
+pub struct HtxSymbols {
+    pub data: Vec<HtxSymbol>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HtxSymbol {
+    pub symbol: String,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub price_precision: u32,
+    pub amount_precision: u32,
+    /// e.g. `online`, `offline`
+    pub state: String,
+}
+
+impl From<HtxSymbol> for InstrumentInfo {
+    fn from(symbol: HtxSymbol) -> Self {
+        Self {
+            trading: symbol.state == "online",
+            symbol: symbol.symbol,
+            base: symbol.base_currency.to_lowercase(),
+            quote: symbol.quote_currency.to_lowercase(),
+            tick_size: Some(decimals_to_size(symbol.price_precision)),
+            lot_size: Some(decimals_to_size(symbol.amount_precision)),
+            status: symbol.state,
+        }
+    }
+}
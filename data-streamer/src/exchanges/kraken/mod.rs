@@ -0,0 +1,481 @@
+pub mod model;
+
+use crate::{
+    book::{LocalBook, ResubscribingBook},
+    catalogue::{decimals_to_size, InstrumentInfo},
+    connector::{ExchangeConnector, StreamEvent},
+    model::{Decimal, OrderBook, OrderBookLevel},
+    reconnect::{SnapshotRetry, SNAPSHOT_TIMEOUT},
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::*;
+use model::{KrakenAssetPairs, KrakenBook, KrakenBookMessage, KrakenMethodResponse};
+use serde_json::{json, Value};
+
+pub const KRAKEN_REST_URL: &str = "https://api.kraken.com";
+pub const KRAKEN_WS_URL: &str = "wss://ws.kraken.com/v2";
+
+/// Assets Kraken names differently depending on the API. `(websocket v2 name, REST name)`
+const ASSET_ALIASES: &[(&str, &str)] = &[("BTC", "XBT"), ("DOGE", "XDG")];
+
+/// Levels of each side included in the checksum
+const CHECKSUM_DEPTH: usize = 10;
+
+fn ws_asset(asset: &str) -> String {
+    let asset = asset.to_uppercase();
+    ASSET_ALIASES
+        .iter()
+        .find(|(_, rest)| *rest == asset)
+        .map_or(asset.clone(), |(ws, _)| ws.to_string())
+}
+
+pub(crate) fn rest_asset(asset: &str) -> String {
+    let asset = asset.to_uppercase();
+    ASSET_ALIASES
+        .iter()
+        .find(|(ws, _)| *ws == asset)
+        .map_or(asset.clone(), |(_, rest)| rest.to_string())
+}
+
+/// Websocket v2 symbol, e.g. `BTC/USD`. Accepts both `btc` and `xbt` as base
+pub fn ws_symbol(instrument: &Instrument) -> String {
+    format!("{}/{}", ws_asset(&instrument.base), ws_asset(&instrument.quote))
+}
+
+/// REST pair name, e.g. `XBTUSD`
+pub fn rest_pair(instrument: &Instrument) -> String {
+    format!("{}{}", rest_asset(&instrument.base), rest_asset(&instrument.quote))
+}
+
+/// Instruments listed in `/0/public/AssetPairs`. Assets are kept with their REST names, e.g. `xbt`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let pairs: KrakenAssetPairs = client
+        .get(format!("{}/0/public/AssetPairs", rest_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(pairs
+        .result
+        .unwrap_or_default()
+        .into_values()
+        .filter_map(|pair| {
+            let wsname = pair.wsname.clone()?;
+            let (base, quote) = wsname.split_once('/')?;
+            let status = pair.status.clone().unwrap_or_else(|| "online".to_string());
+            Some(InstrumentInfo {
+                symbol: wsname.clone(),
+                base: rest_asset(base).to_lowercase(),
+                quote: rest_asset(quote).to_lowercase(),
+                tick_size: Some(decimals_to_size(pair.pair_decimals as u32)),
+                lot_size: Some(decimals_to_size(pair.lot_decimals as u32)),
+                trading: status == "online",
+                status,
+            })
+        })
+        .collect())
+}
+
+/// Kraken's CRC32 over the top 10 asks then the top 10 bids. Each price and quantity is formatted with
+/// the pair precision, stripped of the decimal point and of leading zeros
+fn checksum(book: &LocalBook, price_precision: usize, qty_precision: usize) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    let asks = book.asks();
+    let bids = book.bids();
+    for level in asks.iter().take(CHECKSUM_DEPTH).chain(bids.iter().take(CHECKSUM_DEPTH)) {
+        hasher.update(checksum_field(level.price, price_precision).as_bytes());
+        hasher.update(checksum_field(level.amount, qty_precision).as_bytes());
+    }
+    hasher.finalize()
+}
+
+fn checksum_field(value: Decimal, precision: usize) -> String {
+    format!("{:.*}", precision, value)
+        .replace('.', "")
+        .trim_start_matches('0')
+        .to_string()
+}
+
+/// Streams the v2 `book` channel. Keeps the book at the subscribed depth and verifies the checksum
+/// of every message, resubscribing to get a fresh snapshot when it doesn't match
+#[derive(Debug, Clone)]
+pub struct KrakenConnector {
+    instrument: Instrument,
+    depth: usize,
+    ws_url: String,
+    rest_url: String,
+    client: reqwest::Client,
+    /// `(price, quantity)` decimals of the pair, fetched once from the REST API
+    precision: Option<(usize, usize)>,
+    /// Paces the precision requests while they fail
+    precision_retry: SnapshotRetry,
+    book: ResubscribingBook,
+    /// Kraken v2 has no update id, messages applied since the last snapshot are counted instead
+    sequence: usize,
+    events: Vec<StreamEvent>,
+}
+
+impl KrakenConnector {
+    pub fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            depth: 10,
+            ws_url: KRAKEN_WS_URL.to_string(),
+            rest_url: KRAKEN_REST_URL.to_string(),
+            client: reqwest::Client::new(),
+            precision: None,
+            precision_retry: SnapshotRetry::default(),
+            book: ResubscribingBook::default(),
+            sequence: 0,
+            events: vec![],
+        }
+    }
+
+    /// Book depth to subscribe to. Kraken accepts 10, 25, 100, 500 and 1000
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Overrides the websocket url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    /// Overrides the REST base url the pair precision is fetched from
+    pub fn with_rest_url(mut self, rest_url: impl Into<String>) -> Self {
+        self.rest_url = rest_url.into();
+        self
+    }
+
+    fn request(&self, method: &str) -> String {
+        let mut params = json!({
+            "channel": "book",
+            "symbol": [ws_symbol(&self.instrument)],
+            "depth": self.depth,
+        });
+        if method == "subscribe" {
+            params["snapshot"] = json!(true);
+        }
+        json!({ "method": method, "params": params }).to_string()
+    }
+
+    /// Drops the local book and asks for a fresh snapshot
+    fn resubscribe(&mut self) {
+        let requests = [self.request("unsubscribe"), self.request("subscribe")];
+        self.book.resubscribe(requests);
+    }
+
+    async fn fetch_precision(&self) -> Result<(usize, usize), String> {
+        let pairs: KrakenAssetPairs = self
+            .client
+            .get(format!("{}/0/public/AssetPairs", self.rest_url))
+            .query(&[("pair", rest_pair(&self.instrument))])
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+        pairs
+            .result
+            .and_then(|result| result.into_values().next())
+            .map(|pair| (pair.pair_decimals, pair.lot_decimals))
+            .ok_or_else(|| format!("{:?}", pairs.error))
+    }
+
+    /// Applies a book message. The book is kept while the pair precision can't be fetched, it's verified
+    /// and published from the first message after the precision arrives
+    async fn apply(&mut self, kind: &str, data: KrakenBook) -> Option<OrderBook> {
+        if self.precision.is_none() && self.precision_retry.due() {
+            match self.fetch_precision().await {
+                Ok(precision) => self.precision = Some(precision),
+                Err(err) => {
+                    error!("Couldn't fetch Kraken precision for {}. Error: {}", data.symbol, err);
+                    self.precision_retry.failed();
+                }
+            }
+        }
+        let bids: Vec<OrderBookLevel> = data.bids.into_iter().map(OrderBookLevel::from).collect();
+        let asks: Vec<OrderBookLevel> = data.asks.into_iter().map(OrderBookLevel::from).collect();
+
+        let book = match kind {
+            "snapshot" => {
+                self.sequence = 0;
+                self.book.snapshot(&bids, &asks)
+            }
+            "update" => match self.book.update(&bids, &asks) {
+                Some(book) => book,
+                None => {
+                    self.resubscribe();
+                    return None;
+                }
+            },
+            _ => {
+                warn!("Received non-handled book message from Kraken. Type: {}", kind);
+                return None;
+            }
+        };
+        book.truncate(self.depth);
+        // The checksum covers the whole book, the first verified message verifies the ones before it too
+        let (price_precision, qty_precision) = self.precision?;
+        let local_checksum = checksum(book, price_precision, qty_precision);
+        if local_checksum != data.checksum {
+            warn!(
+                "Kraken checksum mismatch for {}, expected {} got {}. Resubscribing",
+                data.symbol, data.checksum, local_checksum
+            );
+            self.resubscribe();
+            return None;
+        }
+        let book = self.book.book()?;
+        self.sequence += 1;
+        Some(OrderBook {
+            exchange: Exchange::Kraken,
+            sequence: self.sequence,
+            exchange_ts: data.timestamp,
+            received_ts: Utc::now(),
+            instrument: self.instrument.get_symbol_compact(),
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        })
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for KrakenConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Kraken
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![self.instrument.clone()]
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![self.request("subscribe")]
+    }
+
+    fn reset(&mut self) {
+        self.book.reset();
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let parse_error = |err: serde_json::Error| StreamerError::parse(Exchange::Kraken, err, msg);
+        let resp = serde_json::from_str::<Value>(msg).map_err(parse_error)?;
+        if resp.get("method").is_some() {
+            let resp = serde_json::from_value::<KrakenMethodResponse>(resp).map_err(parse_error)?;
+            match (resp.method.as_str(), resp.success) {
+                ("subscribe", true) => self.events.push(StreamEvent::Subscribed {
+                    exchange: Exchange::Kraken,
+                    channels: vec![format!("book:{}", ws_symbol(&self.instrument))],
+                }),
+                (_, true) => info!("Kraken {} succeeded", resp.method),
+                (_, false) => self.events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                    Exchange::Kraken,
+                    resp.error.unwrap_or_default(),
+                ))),
+            }
+            return Ok(None);
+        }
+        match resp.get("channel").and_then(Value::as_str) {
+            Some("book") => {
+                let book_msg = serde_json::from_value::<KrakenBookMessage>(resp).map_err(parse_error)?;
+                let mut last = None;
+                for data in book_msg.data {
+                    last = self.apply(&book_msg.kind, data).await;
+                }
+                Ok(last)
+            }
+            Some("heartbeat") => Ok(None),
+            channel => {
+                warn!("Received non-data message from Kraken. Channel: {:?}", channel);
+                Ok(None)
+            }
+        }
+    }
+
+    fn outgoing(&mut self) -> Vec<String> {
+        self.book.outgoing()
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchanges::stub::StubRest;
+    use std::time::Duration;
+
+    /// Top levels of a BTC/USD book and their checksum, the example of Kraken's v2 book checksum guide.
+    /// The pair is quoted with 1 price and 8 quantity decimals
+    const ASKS: &[(&str, &str)] = &[
+        ("45285.2", "0.00100000"),
+        ("45286.4", "1.54571953"),
+        ("45286.6", "1.54571109"),
+        ("45289.6", "1.54560911"),
+        ("45290.2", "0.15890660"),
+        ("45291.8", "1.54553491"),
+        ("45294.7", "0.04454749"),
+        ("45296.1", "0.35380000"),
+        ("45297.5", "0.09945542"),
+        ("45299.5", "0.18772827"),
+    ];
+    const BIDS: &[(&str, &str)] = &[
+        ("45283.5", "0.10000000"),
+        ("45283.4", "1.54582015"),
+        ("45282.1", "0.10000000"),
+        ("45281.0", "0.10000000"),
+        ("45280.3", "1.54592586"),
+        ("45279.0", "0.07990000"),
+        ("45277.6", "0.03310103"),
+        ("45277.5", "0.30000000"),
+        ("45277.3", "1.54602737"),
+        ("45276.6", "0.15445238"),
+    ];
+    const CHECKSUM: u32 = 3310070434;
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<OrderBookLevel> {
+        levels
+            .iter()
+            .map(|(price, amount)| OrderBookLevel {
+                price: price.parse().unwrap(),
+                amount: amount.parse().unwrap(),
+            })
+            .collect()
+    }
+
+    /// The levels as Kraken sends them, JSON numbers dropping the trailing zeros
+    fn json_levels(levels: &[(&str, &str)]) -> Value {
+        levels
+            .iter()
+            .map(|(price, qty)| {
+                let number = |value: &str| value.parse::<f64>().unwrap();
+                json!({ "price": number(price), "qty": number(qty) })
+            })
+            .collect()
+    }
+
+    fn message(kind: &str, checksum: u32) -> String {
+        json!({
+            "channel": "book",
+            "type": kind,
+            "data": [{
+                "symbol": "BTC/USD",
+                "bids": json_levels(BIDS),
+                "asks": json_levels(ASKS),
+                "checksum": checksum
+            }]
+        })
+        .to_string()
+    }
+
+    fn snapshot(checksum: u32) -> String {
+        message("snapshot", checksum)
+    }
+
+    fn connector() -> KrakenConnector {
+        let mut connector = KrakenConnector::new(Instrument {
+            base: "btc".to_string(),
+            quote: "usd".to_string(),
+        });
+        connector.precision = Some((1, 8));
+        connector
+    }
+
+    #[test]
+    fn checksum_matches_the_documented_example() {
+        let book = LocalBook::from_snapshot(&levels(BIDS), &levels(ASKS));
+        assert_eq!(checksum(&book, 1, 8), CHECKSUM);
+    }
+
+    #[test]
+    fn checksum_fields_are_padded_to_the_precision_without_point_and_leading_zeros() {
+        let field = |value: &str, precision| checksum_field(value.parse().unwrap(), precision);
+        assert_eq!(field("45285.2", 1), "452852");
+        // Trailing zeros dropped by the JSON numbers are restored
+        assert_eq!(field("45281", 1), "452810");
+        assert_eq!(field("0.0799", 8), "7990000");
+        assert_eq!(field("0.001", 8), "100000");
+        assert_eq!(field("1.54571953", 8), "154571953");
+    }
+
+    #[test]
+    fn levels_keep_the_exact_decimals_of_the_json_numbers() {
+        let level: model::KrakenLevel =
+            serde_json::from_value(serde_json::from_str(r#"{"price":45283.5,"qty":0.12345678901234567891}"#).unwrap())
+                .unwrap();
+        assert_eq!(level.qty.to_string(), "0.12345678901234567891");
+        assert_eq!(level.price.to_string(), "45283.5");
+    }
+
+    #[tokio::test]
+    async fn snapshot_is_verified_against_its_checksum() {
+        let mut connector = connector();
+        let book = connector.parse(&snapshot(CHECKSUM)).await.unwrap().unwrap();
+        assert_eq!(book.asks, levels(ASKS));
+        assert_eq!(book.bids, levels(BIDS));
+        assert!(connector.outgoing().is_empty());
+    }
+
+    #[tokio::test]
+    async fn book_is_kept_until_the_precision_is_fetched() {
+        let rest = StubRest::start(&[
+            json!({ "error": ["EGeneral:Temporary lockout"] }).to_string(),
+            json!({
+                "error": [],
+                "result": { "XXBTZUSD": { "pair_decimals": 1, "lot_decimals": 8, "wsname": "XBT/USD" } }
+            })
+            .to_string(),
+        ])
+        .await;
+        let mut connector = connector().with_rest_url(rest.url());
+        connector.precision = None;
+        connector.resubscribe();
+        assert_eq!(connector.outgoing().len(), 2);
+
+        // The snapshot answering the resubscription can't be verified yet, it's kept rather than dropped
+        assert!(connector.parse(&snapshot(CHECKSUM)).await.unwrap().is_none());
+        assert!(connector.parse(&message("update", CHECKSUM)).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 1);
+        assert!(connector.outgoing().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let book = connector.parse(&message("update", CHECKSUM)).await.unwrap().unwrap();
+        assert_eq!(rest.requests(), 2);
+        assert_eq!(book.bids, levels(BIDS));
+        assert!(connector.outgoing().is_empty());
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch_resubscribes() {
+        let mut connector = connector();
+        assert!(connector.parse(&snapshot(CHECKSUM + 1)).await.unwrap().is_none());
+        let outgoing: Vec<Value> = connector
+            .outgoing()
+            .iter()
+            .map(|msg| serde_json::from_str(msg).unwrap())
+            .collect();
+        let methods: Vec<&str> = outgoing.iter().filter_map(|msg| msg["method"].as_str()).collect();
+        assert_eq!(methods, ["unsubscribe", "subscribe"]);
+        // Resubscribed once, the updates until the fresh snapshot are dropped
+        assert!(connector.parse(&message("update", CHECKSUM)).await.unwrap().is_none());
+        assert!(connector.outgoing().is_empty());
+        assert!(connector.parse(&snapshot(CHECKSUM)).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::model::{Decimal, OrderBookLevel};
+
+/// Kraken v2 sends prices and quantities as JSON numbers, read from their text as serde_json's
+/// `arbitrary_precision` keeps it rather than going through `f64`
+#[derive(Debug, Deserialize, Clone)]
+pub struct KrakenLevel {
+    pub price: Decimal,
+    pub qty: Decimal,
+}
+
+impl From<KrakenLevel> for OrderBookLevel {
+    fn from(level: KrakenLevel) -> Self {
+        Self {
+            price: level.price,
+            amount: level.qty,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KrakenBook {
+    pub symbol: String,
+    pub bids: Vec<KrakenLevel>,
+    pub asks: Vec<KrakenLevel>,
+    pub checksum: u32,
+    /// Sent on updates only
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Messages of the v2 `book` channel
+#[derive(Debug, Deserialize)]
+pub struct KrakenBookMessage {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub data: Vec<KrakenBook>,
+}
+
+/// Response to a `subscribe`/`unsubscribe` request
+#[derive(Debug, Deserialize)]
+pub struct KrakenMethodResponse {
+    pub method: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// `/0/public/AssetPairs` response
+#[derive(Debug, Deserialize)]
+pub struct KrakenAssetPairs {
+    pub error: Vec<String>,
+    pub result: Option<HashMap<String, KrakenAssetPair>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KrakenAssetPair {
+    pub pair_decimals: usize,
+    pub lot_decimals: usize,
+    /// Websocket name, e.g. `XBT/USD`. Missing for pairs not available on the websocket API
+    pub wsname: Option<String>,
+    /// e.g. `online`, `cancel_only`
+    pub status: Option<String>,
+}
@@ -0,0 +1,360 @@
+pub mod model;
+
+use crate::{
+    book::LocalBook,
+    catalogue::InstrumentInfo,
+    connector::{ExchangeConnector, StreamEvent, Heartbeat},
+    model::{OrderBook, OrderBookLevel},
+    reconnect::{SnapshotRetry, SNAPSHOT_TIMEOUT},
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::*;
+use model::{
+    KucoinBulletResponse, KucoinChange, KucoinMessage, KucoinSnapshot, KucoinSnapshotResponse, KucoinSymbols,
+};
+use serde_json::json;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const KUCOIN_REST_URL: &str = "https://api.kucoin.com";
+/// Shortest ping interval used, whatever the handshake hands out
+pub const MIN_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Instruments listed in `/api/v1/symbols`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let symbols: KucoinSymbols = client
+        .get(format!("{}/api/v1/symbols", rest_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(symbols.data.into_iter().map(Into::into).collect())
+}
+
+/// KuCoin symbol, e.g. `BTC-USDT`
+pub fn symbol(instrument: &Instrument) -> String {
+    instrument.get_symbol_dash().to_uppercase()
+}
+
+/// Applies the changes newer than `sequence` to one side of the book
+fn apply_changes(changes: &[KucoinChange], sequence: usize, update: impl FnOnce(&[OrderBookLevel])) {
+    let levels: Vec<OrderBookLevel> = changes
+        .iter()
+        .filter(|change| change.sequence > sequence)
+        .map(|change| OrderBookLevel {
+            price: change.price,
+            amount: change.size,
+        })
+        .collect();
+    update(&levels);
+}
+
+/// Streams `/market/level2:<symbol>` and maintains the local book, seeded from the public 100 level
+/// REST snapshot, the full depth one requiring an API key. Only the top 100 levels are complete then:
+/// the deeper ones are the levels changed since the snapshot, and the levels the stream leaves untouched
+/// are missing from them, also once top levels are cancelled and the deeper ones move up.
+/// Every (re)connection starts with the `bullet-public` handshake, which hands out the token,
+/// the websocket endpoint and the ping interval to use
+#[derive(Debug, Clone)]
+pub struct KucoinConnector {
+    instrument: Instrument,
+    rest_url: String,
+    client: reqwest::Client,
+    endpoint: String,
+    /// Replaces the endpoint handed out by the handshake
+    ws_url: Option<String>,
+    ping_interval: Duration,
+    book: Option<LocalBook>,
+    sequence: usize,
+    /// Paces the snapshot requests while the book isn't synchronised
+    retry: SnapshotRetry,
+    events: Vec<StreamEvent>,
+}
+
+impl KucoinConnector {
+    pub fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            rest_url: KUCOIN_REST_URL.to_string(),
+            client: reqwest::Client::new(),
+            endpoint: "wss://ws-api-spot.kucoin.com".to_string(),
+            ws_url: None,
+            ping_interval: Duration::from_secs(18),
+            book: None,
+            sequence: 0,
+            retry: SnapshotRetry::default(),
+            events: vec![],
+        }
+    }
+
+    /// Overrides the REST base url used for the handshake and the snapshot
+    pub fn with_rest_url(mut self, rest_url: impl Into<String>) -> Self {
+        self.rest_url = rest_url.into();
+        self
+    }
+
+    /// Connects to this websocket url instead of the one handed out by the handshake, still with its token
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = Some(ws_url.into());
+        self
+    }
+
+    async fn fetch_bullet(&self) -> Result<KucoinBulletResponse, reqwest::Error> {
+        self.client
+            .post(format!("{}/api/v1/bullet-public", self.rest_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn fetch_snapshot(&self) -> Result<KucoinSnapshotResponse, reqwest::Error> {
+        self.client
+            .get(format!("{}/api/v1/market/orderbook/level2_100", self.rest_url))
+            .query(&[("symbol", symbol(&self.instrument))])
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Replaces the local book with a fresh snapshot. The snapshot has to reach the update
+    /// `sequence_start`, otherwise it's discarded and fetched again once the retry is due
+    async fn sync(&mut self, sequence_start: usize) -> Option<()> {
+        let snapshot: KucoinSnapshot = match self.fetch_snapshot().await {
+            Ok(KucoinSnapshotResponse { data: Some(data), .. }) => data,
+            Ok(resp) => {
+                error!("Couldn't fetch KuCoin orderbook snapshot. Code: {}", resp.code);
+                return None;
+            }
+            Err(err) => {
+                error!("Couldn't fetch KuCoin orderbook snapshot. Error: {:?}", err);
+                return None;
+            }
+        };
+        if snapshot.sequence + 1 < sequence_start {
+            warn!(
+                "KuCoin snapshot {} is older than the first buffered update {}, fetching it again",
+                snapshot.sequence, sequence_start
+            );
+            return None;
+        }
+        info!("Synchronised KuCoin local book at sequence {}", snapshot.sequence);
+        self.book = Some(LocalBook::from_snapshot(&snapshot.bids, &snapshot.asks));
+        self.sequence = snapshot.sequence;
+        self.retry = SnapshotRetry::default();
+        Some(())
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for KucoinConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Kucoin
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![self.instrument.clone()]
+    }
+
+    /// Endpoint handed out by the last handshake
+    fn endpoint(&self) -> String {
+        self.endpoint.clone()
+    }
+
+    async fn resolve_endpoint(&mut self) -> Option<String> {
+        let bullet = match self.fetch_bullet().await {
+            Ok(KucoinBulletResponse { data: Some(data), .. }) => data,
+            Ok(resp) => {
+                error!("KuCoin handshake failed. Code: {}", resp.code);
+                return None;
+            }
+            Err(err) => {
+                error!("KuCoin handshake failed. Error: {:?}", err);
+                return None;
+            }
+        };
+        let server = bullet.instance_servers.into_iter().next()?;
+        self.endpoint = self.ws_url.clone().unwrap_or(server.endpoint);
+        // A zero interval would panic the heartbeat timer
+        self.ping_interval = Duration::from_millis(server.ping_interval).max(MIN_PING_INTERVAL);
+        let connect_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis())
+            .unwrap_or_default();
+        Some(format!("{}?token={}&connectId={}", self.endpoint, bullet.token, connect_id))
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![json!({
+            "id": "level2",
+            "type": "subscribe",
+            "topic": format!("/market/level2:{}", symbol(&self.instrument)),
+            "privateChannel": false,
+            "response": true
+        })
+        .to_string()]
+    }
+
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::Message {
+            interval: self.ping_interval,
+            message: json!({ "id": "ping", "type": "ping" }).to_string(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.book = None;
+        self.retry = SnapshotRetry::default();
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let message =
+            serde_json::from_str::<KucoinMessage>(msg).map_err(|err| StreamerError::parse(Exchange::Kucoin, err, msg))?;
+        let update = match message {
+            KucoinMessage::Message(message) => message.data,
+            KucoinMessage::Ack {} => {
+                self.events.push(StreamEvent::Subscribed {
+                    exchange: Exchange::Kucoin,
+                    channels: vec![format!("/market/level2:{}", symbol(&self.instrument))],
+                });
+                return Ok(None);
+            }
+            KucoinMessage::Error(err) => {
+                self.events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                    Exchange::Kucoin,
+                    format!("{}: {}", err.code.unwrap_or_default(), err.data.unwrap_or_default()),
+                )));
+                return Ok(None);
+            }
+            KucoinMessage::Welcome {} | KucoinMessage::Pong {} | KucoinMessage::Other => return Ok(None),
+        };
+
+        if self.book.is_some() && update.sequence_start > self.sequence + 1 {
+            warn!(
+                "Gap in KuCoin level2 stream, expected sequence {} got {}. Resynchronising",
+                self.sequence + 1,
+                update.sequence_start
+            );
+            self.book = None;
+        }
+        if self.book.is_none() {
+            if !self.retry.due() {
+                return Ok(None);
+            }
+            if self.sync(update.sequence_start).await.is_none() {
+                self.retry.failed();
+                return Ok(None);
+            }
+        }
+        // Already part of the snapshot
+        if update.sequence_end <= self.sequence {
+            return Ok(None);
+        }
+
+        let sequence = self.sequence;
+        let Some(book) = self.book.as_mut() else {
+            return Ok(None);
+        };
+        apply_changes(&update.changes.bids, sequence, |levels| book.update_bids(levels));
+        apply_changes(&update.changes.asks, sequence, |levels| book.update_asks(levels));
+        self.sequence = update.sequence_end;
+        Ok(Some(OrderBook {
+            exchange: Exchange::Kucoin,
+            sequence: self.sequence,
+            exchange_ts: update.time.and_then(DateTime::from_timestamp_millis),
+            received_ts: Utc::now(),
+            instrument: self.instrument.get_symbol_compact(),
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        }))
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchanges::stub::StubRest;
+
+    fn instrument() -> Instrument {
+        Instrument {
+            base: "btc".to_string(),
+            quote: "usdt".to_string(),
+        }
+    }
+
+    fn snapshot(sequence: usize) -> String {
+        json!({
+            "code": "200000",
+            "data": {
+                "sequence": sequence.to_string(),
+                "bids": [["100.0", "1.0"]],
+                "asks": [["101.0", "1.0"]]
+            }
+        })
+        .to_string()
+    }
+
+    fn update(sequence_start: usize, sequence_end: usize) -> String {
+        json!({
+            "type": "message",
+            "topic": "/market/level2:BTC-USDT",
+            "subject": "trade.l2update",
+            "data": {
+                "sequenceStart": sequence_start,
+                "sequenceEnd": sequence_end,
+                "time": 1700000000000i64,
+                "changes": {
+                    "bids": [["99.0", "2.0", sequence_end.to_string()]],
+                    "asks": []
+                }
+            }
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn zero_ping_interval_is_clamped() {
+        let bullet = json!({
+            "code": "200000",
+            "data": {
+                "token": "token",
+                "instanceServers": [{ "endpoint": "wss://ws-api-spot.kucoin.com", "pingInterval": 0 }]
+            }
+        })
+        .to_string();
+        let rest = StubRest::start(&[bullet]).await;
+        let mut connector = KucoinConnector::new(instrument()).with_rest_url(rest.url());
+        assert!(connector.resolve_endpoint().await.is_some());
+        let Heartbeat::Message { interval, .. } = connector.heartbeat() else {
+            panic!("KuCoin pings with messages");
+        };
+        assert_eq!(interval, MIN_PING_INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn unusable_snapshot_is_retried_with_backoff() {
+        let rest = StubRest::start(&[snapshot(10), snapshot(200)]).await;
+        let mut connector = KucoinConnector::new(instrument()).with_rest_url(rest.url());
+        assert!(connector.parse(&update(100, 100)).await.unwrap().is_none());
+        assert!(connector.parse(&update(101, 101)).await.unwrap().is_none());
+        assert_eq!(rest.requests(), 1);
+
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let book = connector.parse(&update(199, 201)).await.unwrap().unwrap();
+        assert_eq!(rest.requests(), 2);
+        assert_eq!(book.sequence, 201);
+        assert_eq!(book.bids.len(), 2);
+    }
+}
@@ -0,0 +1,123 @@
+use serde::Deserialize;
+
+use crate::{
+    catalogue::InstrumentInfo,
+    model::{de_usize_from_str, Decimal, OrderBookLevel},
+};
+
+/// `POST /api/v1/bullet-public` response
+#[derive(Debug, Deserialize)]
+pub struct KucoinBulletResponse {
+    pub code: String,
+    pub data: Option<KucoinBullet>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KucoinBullet {
+    pub token: String,
+    pub instance_servers: Vec<KucoinInstanceServer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KucoinInstanceServer {
+    pub endpoint: String,
+    /// Milliseconds
+    pub ping_interval: u64,
+}
+
+/// `GET /api/v1/market/orderbook/level2_100` response
+#[derive(Debug, Deserialize)]
+pub struct KucoinSnapshotResponse {
+    pub code: String,
+    pub data: Option<KucoinSnapshot>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KucoinSnapshot {
+    #[serde(deserialize_with = "de_usize_from_str")]
+    pub sequence: usize,
+    pub bids: Vec<OrderBookLevel>,
+    pub asks: Vec<OrderBookLevel>,
+}
+
+/// Websocket messages, tagged by `type`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KucoinMessage {
+    Welcome {},
+    Ack {},
+    Pong {},
+    Message(KucoinL2Message),
+    Error(KucoinError),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KucoinL2Message {
+    pub data: KucoinL2Update,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KucoinL2Update {
+    pub sequence_start: usize,
+    pub sequence_end: usize,
+    /// Milliseconds
+    pub time: Option<i64>,
+    pub changes: KucoinChanges,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KucoinChanges {
+    pub bids: Vec<KucoinChange>,
+    pub asks: Vec<KucoinChange>,
+}
+
+/// `[price, size, sequence]`, the size is the new absolute amount at that price
+#[derive(Debug, Deserialize)]
+pub struct KucoinChange {
+    pub price: Decimal,
+    pub size: Decimal,
+    #[serde(deserialize_with = "de_usize_from_str")]
+    pub sequence: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KucoinError {
+    pub code: Option<u64>,
+    pub data: Option<String>,
+}
+
+/// `/api/v1/symbols` response
+#[derive(Debug, Deserialize)]
+pub struct KucoinSymbols {
+    pub data: Vec<KucoinSymbol>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KucoinSymbol {
+    pub symbol: String,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub price_increment: String,
+    pub base_increment: String,
+    pub enable_trading: bool,
+}
+
+impl From<KucoinSymbol> for InstrumentInfo {
+    fn from(symbol: KucoinSymbol) -> Self {
+        Self {
+            symbol: symbol.symbol,
+            base: symbol.base_currency.to_lowercase(),
+            quote: symbol.quote_currency.to_lowercase(),
+            tick_size: symbol.price_increment.parse().ok(),
+            lot_size: symbol.base_increment.parse().ok(),
+            status: if symbol.enable_trading { "enabled" } else { "disabled" }.to_string(),
+            trading: symbol.enable_trading,
+        }
+    }
+}
@@ -1,2 +1,185 @@
 pub mod binance;
 pub mod bitstamp;
+pub mod bybit;
+pub mod coinbase;
+pub mod htx;
+pub mod kraken;
+pub mod kucoin;
+pub mod okx;
+#[cfg(test)]
+pub(crate) mod stub;
+
+use crate::{catalogue::InstrumentInfo, connector::ExchangeConnector, BookMode, Exchange, Instrument, VenueEndpoints};
+use bybit::BybitCategory;
+
+/// Built-in connectors streaming `instruments` from the given exchange. Venues that multiplex instruments
+/// over one websocket get a connector per shard of instruments, the others a connector per instrument.
+/// Empty for exchanges this crate doesn't implement, those need a connector registered through
+/// `Subscriptions::with_connector`. The connectors reach the exchange at `endpoints`, Bybit's stream `bybit_category`
+pub fn connectors_for(
+    exchange: &Exchange,
+    instruments: &[Instrument],
+    mode: BookMode,
+    endpoints: &VenueEndpoints,
+    bybit_category: BybitCategory,
+) -> Vec<Box<dyn ExchangeConnector>> {
+    match exchange {
+        Exchange::Binance => instruments
+            .chunks(binance::BINANCE_MAX_STREAMS)
+            .map(|shard| match mode {
+                BookMode::Snapshot => Box::new(endpoints.ws(
+                    binance::BinanceConnector::new(shard.to_vec()),
+                    binance::BinanceConnector::with_ws_url,
+                )) as Box<dyn ExchangeConnector>,
+                BookMode::Diff => {
+                    let connector = binance::BinanceDiffConnector::new(shard.to_vec());
+                    let connector = endpoints.ws(connector, binance::BinanceDiffConnector::with_ws_url);
+                    Box::new(endpoints.rest(connector, binance::BinanceDiffConnector::with_rest_url))
+                }
+            })
+            .collect(),
+        Exchange::Bitstamp => instruments
+            .chunks(bitstamp::BITSTAMP_MAX_CHANNELS)
+            .map(|shard| match mode {
+                BookMode::Snapshot => Box::new(endpoints.ws(
+                    bitstamp::BitstampConnector::new(shard.to_vec()),
+                    bitstamp::BitstampConnector::with_ws_url,
+                )) as Box<dyn ExchangeConnector>,
+                BookMode::Diff => {
+                    let connector = bitstamp::BitstampDiffConnector::new(shard.to_vec());
+                    let connector = endpoints.ws(connector, bitstamp::BitstampDiffConnector::with_ws_url);
+                    Box::new(endpoints.rest(connector, bitstamp::BitstampDiffConnector::with_rest_url))
+                }
+            })
+            .collect(),
+        _ => instruments
+            .iter()
+            .filter_map(|instrument| connector_for(exchange, instrument, mode, endpoints, bybit_category))
+            .collect(),
+    }
+}
+
+/// Built-in connectors publishing the trades of `instruments` on the given exchange, sharded like the books.
+/// Empty for exchanges without a built-in trade connector
+pub fn trade_connectors_for(
+    exchange: &Exchange,
+    instruments: &[Instrument],
+    endpoints: &VenueEndpoints,
+) -> Vec<Box<dyn ExchangeConnector>> {
+    match exchange {
+        Exchange::Binance => instruments
+            .chunks(binance::BINANCE_MAX_STREAMS)
+            .map(|shard| {
+                Box::new(endpoints.ws(
+                    binance::BinanceTradeConnector::new(shard.to_vec()),
+                    binance::BinanceTradeConnector::with_ws_url,
+                )) as Box<dyn ExchangeConnector>
+            })
+            .collect(),
+        Exchange::Bitstamp => instruments
+            .chunks(bitstamp::BITSTAMP_MAX_CHANNELS)
+            .map(|shard| {
+                Box::new(endpoints.ws(
+                    bitstamp::BitstampTradeConnector::new(shard.to_vec()),
+                    bitstamp::BitstampTradeConnector::with_ws_url,
+                )) as Box<dyn ExchangeConnector>
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Built-in single instrument connector for the exchanges that don't multiplex instruments
+fn connector_for(
+    exchange: &Exchange,
+    instrument: &Instrument,
+    mode: BookMode,
+    endpoints: &VenueEndpoints,
+    bybit_category: BybitCategory,
+) -> Option<Box<dyn ExchangeConnector>> {
+    let instrument = instrument.clone();
+    match (exchange, mode) {
+        (Exchange::Kraken, _) => {
+            let connector = endpoints.ws(kraken::KrakenConnector::new(instrument), kraken::KrakenConnector::with_ws_url);
+            Some(Box::new(endpoints.rest(connector, kraken::KrakenConnector::with_rest_url)))
+        }
+        (Exchange::Coinbase, _) => Some(Box::new(endpoints.ws(
+            coinbase::CoinbaseConnector::new(instrument),
+            coinbase::CoinbaseConnector::with_ws_url,
+        ))),
+        (Exchange::Okx, BookMode::Snapshot) => Some(Box::new(endpoints.ws(
+            okx::OkxConnector::new(instrument).with_channel(okx::OkxChannel::Books5),
+            okx::OkxConnector::with_ws_url,
+        ))),
+        (Exchange::Okx, BookMode::Diff) => Some(Box::new(
+            endpoints.ws(okx::OkxConnector::new(instrument), okx::OkxConnector::with_ws_url),
+        )),
+        (Exchange::Bybit, _) => Some(Box::new(endpoints.ws(
+            bybit::BybitConnector::new(instrument).with_category(bybit_category),
+            bybit::BybitConnector::with_ws_url,
+        ))),
+        (Exchange::Htx, _) => Some(Box::new(
+            endpoints.ws(htx::HtxConnector::new(instrument), htx::HtxConnector::with_ws_url),
+        )),
+        (Exchange::Kucoin, _) => {
+            let connector = endpoints.ws(kucoin::KucoinConnector::new(instrument), kucoin::KucoinConnector::with_ws_url);
+            Some(Box::new(endpoints.rest(connector, kucoin::KucoinConnector::with_rest_url)))
+        }
+        (Exchange::Binance | Exchange::Bitstamp | Exchange::Other(_), _) => None,
+    }
+}
+
+/// REST base url of the built-in exchanges
+pub fn rest_url(exchange: &Exchange) -> Option<&'static str> {
+    match exchange {
+        Exchange::Binance => Some(binance::BINANCE_REST_URL),
+        Exchange::Bitstamp => Some(bitstamp::BITSTAMP_REST_URL),
+        Exchange::Kraken => Some(kraken::KRAKEN_REST_URL),
+        Exchange::Coinbase => Some(coinbase::COINBASE_REST_URL),
+        Exchange::Okx => Some(okx::OKX_REST_URL),
+        Exchange::Bybit => Some(bybit::BYBIT_REST_URL),
+        Exchange::Htx => Some(htx::HTX_REST_URL),
+        Exchange::Kucoin => Some(kucoin::KUCOIN_REST_URL),
+        Exchange::Other(_) => None,
+    }
+}
+
+/// Market of the exchange's listing, for the exchanges listing several, e.g. Bybit's `linear`
+pub fn market(exchange: &Exchange, bybit_category: BybitCategory) -> Option<&'static str> {
+    match exchange {
+        Exchange::Bybit => Some(bybit_category.name()),
+        _ => None,
+    }
+}
+
+/// Instruments listed by a built-in exchange, Bybit's of `bybit_category`. `None` for exchanges this crate
+/// doesn't implement
+pub async fn fetch_instruments(
+    exchange: &Exchange,
+    client: &reqwest::Client,
+    rest_url: &str,
+    bybit_category: BybitCategory,
+) -> Option<Result<Vec<InstrumentInfo>, reqwest::Error>> {
+    Some(match exchange {
+        Exchange::Binance => binance::fetch_instruments(client, rest_url).await,
+        Exchange::Bitstamp => bitstamp::fetch_instruments(client, rest_url).await,
+        Exchange::Kraken => kraken::fetch_instruments(client, rest_url).await,
+        Exchange::Coinbase => coinbase::fetch_instruments(client, rest_url).await,
+        Exchange::Okx => okx::fetch_instruments(client, rest_url).await,
+        Exchange::Bybit => bybit::fetch_instruments(client, rest_url, bybit_category).await,
+        Exchange::Htx => htx::fetch_instruments(client, rest_url).await,
+        Exchange::Kucoin => kucoin::fetch_instruments(client, rest_url).await,
+        Exchange::Other(_) => return None,
+    })
+}
+
+/// `(base, quote)` of an instrument as named in the exchange's [InstrumentInfo]
+pub fn catalogue_assets(exchange: &Exchange, instrument: &Instrument) -> (String, String) {
+    match exchange {
+        Exchange::Kraken => (
+            kraken::rest_asset(&instrument.base).to_lowercase(),
+            kraken::rest_asset(&instrument.quote).to_lowercase(),
+        ),
+        _ => (instrument.base.to_lowercase(), instrument.quote.to_lowercase()),
+    }
+}
@@ -0,0 +1,385 @@
+pub mod model;
+
+use crate::{
+    book::{LocalBook, ResubscribingBook},
+    catalogue::InstrumentInfo,
+    connector::{ExchangeConnector, StreamEvent},
+    model::{OrderBook, OrderBookLevel},
+    Exchange, Instrument, StreamerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::*;
+use model::{OkxBook, OkxBookMessage, OkxEvent, OkxInstruments};
+use serde_json::{json, Value};
+
+pub const OKX_REST_URL: &str = "https://www.okx.com";
+pub const OKX_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+
+/// Spot instruments listed in `/api/v5/public/instruments`
+pub async fn fetch_instruments(client: &reqwest::Client, rest_url: &str) -> Result<Vec<InstrumentInfo>, reqwest::Error> {
+    let instruments: OkxInstruments = client
+        .get(format!("{}/api/v5/public/instruments", rest_url))
+        .query(&[("instType", "SPOT")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(instruments.data.into_iter().map(Into::into).collect())
+}
+
+/// Levels of each side included in the checksum
+const CHECKSUM_DEPTH: usize = 25;
+
+/// OKX `instId`, e.g. `BTC-USDT`
+pub fn inst_id(instrument: &Instrument) -> String {
+    instrument.get_symbol_dash().to_uppercase()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OkxChannel {
+    /// 400 levels, a snapshot followed by incremental updates every 100ms
+    Books,
+    /// 5 levels snapshot every 100ms
+    Books5,
+}
+
+impl OkxChannel {
+    fn name(&self) -> &'static str {
+        match self {
+            OkxChannel::Books => "books",
+            OkxChannel::Books5 => "books5",
+        }
+    }
+}
+
+/// OKX's CRC32 over the top 25 levels, see [checksum_string]. Interpreted as a signed 32 bits integer
+fn checksum(book: &LocalBook) -> i32 {
+    crc32fast::hash(checksum_string(book).as_bytes()) as i32
+}
+
+/// The top 25 levels, bids and asks interleaved as `bid:size:ask:size:...`. A side running out of levels
+/// leaves the rest to the other one. Decimals keep the scale they were parsed with, so formatting the levels
+/// gives back the original strings
+fn checksum_string(book: &LocalBook) -> String {
+    let bids = book.bids();
+    let asks = book.asks();
+    let mut fields = vec![];
+    for i in 0..CHECKSUM_DEPTH {
+        if let Some(bid) = bids.get(i) {
+            fields.push(format!("{}:{}", bid.price, bid.amount));
+        }
+        if let Some(ask) = asks.get(i) {
+            fields.push(format!("{}:{}", ask.price, ask.amount));
+        }
+    }
+    fields.join(":")
+}
+
+/// Streams the `books` or `books5` channel. Verifies the checksum and the `prevSeqId`/`seqId` continuity
+/// of every message, resubscribing to get a fresh snapshot when either check fails
+#[derive(Debug, Clone)]
+pub struct OkxConnector {
+    instrument: Instrument,
+    channel: OkxChannel,
+    ws_url: String,
+    book: ResubscribingBook,
+    seq_id: i64,
+    events: Vec<StreamEvent>,
+}
+
+impl OkxConnector {
+    pub fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            channel: OkxChannel::Books,
+            ws_url: OKX_WS_URL.to_string(),
+            book: ResubscribingBook::default(),
+            seq_id: -1,
+            events: vec![],
+        }
+    }
+
+    pub fn with_channel(mut self, channel: OkxChannel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Overrides the websocket url, e.g. to test against a local stand-in
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = ws_url.into();
+        self
+    }
+
+    fn request(&self, op: &str) -> String {
+        json!({
+            "op": op,
+            "args": [{
+                "channel": self.channel.name(),
+                "instId": inst_id(&self.instrument)
+            }]
+        })
+        .to_string()
+    }
+
+    /// Drops the local book and asks for a fresh snapshot
+    fn resubscribe(&mut self) {
+        let requests = [self.request("unsubscribe"), self.request("subscribe")];
+        self.book.resubscribe(requests);
+    }
+
+    fn apply(&mut self, action: &str, data: OkxBook) -> Option<OrderBook> {
+        let bids: Vec<OrderBookLevel> = data.bids.into_iter().map(OrderBookLevel::from).collect();
+        let asks: Vec<OrderBookLevel> = data.asks.into_iter().map(OrderBookLevel::from).collect();
+
+        match action {
+            "snapshot" => {
+                self.book.snapshot(&bids, &asks);
+            }
+            "update" => {
+                if let Some(prev_seq_id) = data.prev_seq_id.filter(|_| self.book.book().is_some()) {
+                    if prev_seq_id != self.seq_id {
+                        warn!(
+                            "Gap in OKX {} stream, expected prevSeqId {} got {}. Resubscribing",
+                            self.channel.name(),
+                            self.seq_id,
+                            prev_seq_id
+                        );
+                        self.resubscribe();
+                        return None;
+                    }
+                }
+                if self.book.update(&bids, &asks).is_none() {
+                    self.resubscribe();
+                    return None;
+                }
+            }
+            _ => {
+                warn!("Received non-handled book message from OKX. Action: {}", action);
+                return None;
+            }
+        }
+        self.seq_id = data.seq_id.unwrap_or(self.seq_id);
+        let exchange_ts = data
+            .ts
+            .and_then(|ts| ts.parse().ok())
+            .and_then(DateTime::from_timestamp_millis);
+
+        let book = self.book.book()?;
+        if let Some(expected) = data.checksum {
+            let local_checksum = checksum(book);
+            if local_checksum != expected {
+                warn!(
+                    "OKX checksum mismatch, expected {} got {}. Resubscribing",
+                    expected, local_checksum
+                );
+                self.resubscribe();
+                return None;
+            }
+        }
+        Some(OrderBook {
+            exchange: Exchange::Okx,
+            sequence: self.seq_id.max(0) as usize,
+            exchange_ts,
+            received_ts: Utc::now(),
+            instrument: self.instrument.get_symbol_compact(),
+            bids: book.bids(),
+            asks: book.asks(),
+            substitutions: vec![],
+        })
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for OkxConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Okx
+    }
+
+    fn instruments(&self) -> Vec<Instrument> {
+        vec![self.instrument.clone()]
+    }
+
+    fn endpoint(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![self.request("subscribe")]
+    }
+
+    fn reset(&mut self) {
+        self.book.reset();
+        self.seq_id = -1;
+        self.events.clear();
+    }
+
+    async fn parse(&mut self, msg: &str) -> Result<Option<OrderBook>, StreamerError> {
+        let parse_error = |err: serde_json::Error| StreamerError::parse(Exchange::Okx, err, msg);
+        let resp = serde_json::from_str::<Value>(msg).map_err(parse_error)?;
+        if resp.get("event").is_some() {
+            let event = serde_json::from_value::<OkxEvent>(resp).map_err(parse_error)?;
+            match (event.event.as_str(), event.arg) {
+                ("subscribe", Some(arg)) => self.events.push(StreamEvent::Subscribed {
+                    exchange: Exchange::Okx,
+                    channels: vec![format!("{}:{}", arg.channel, arg.inst_id)],
+                }),
+                ("error", _) => self.events.push(StreamEvent::Error(StreamerError::subscription_rejected(
+                    Exchange::Okx,
+                    format!("{}: {}", event.code.unwrap_or_default(), event.msg.unwrap_or_default()),
+                ))),
+                // Sent ahead of a service upgrade closing the connection
+                ("notice", _) if event.code.as_deref() == Some("64008") => {
+                    return Err(StreamerError::ReconnectRequested { exchange: Exchange::Okx })
+                }
+                (event, _) => info!("Received {} event from OKX", event),
+            }
+            return Ok(None);
+        }
+        let book_msg = serde_json::from_value::<OkxBookMessage>(resp).map_err(parse_error)?;
+        // `books5` pushes full snapshots without an action
+        let action = book_msg.action.unwrap_or_else(|| "snapshot".to_string());
+        let mut last = None;
+        for data in book_msg.data {
+            last = self.apply(&action, data);
+        }
+        Ok(last)
+    }
+
+    fn outgoing(&mut self) -> Vec<String> {
+        self.book.outgoing()
+    }
+
+    fn events(&mut self) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `[price, size, deprecated, orders]` levels as OKX sends them
+    fn levels(levels: &[(&str, &str)]) -> Value {
+        levels
+            .iter()
+            .map(|(price, size)| json!([price, size, "0", "1"]))
+            .collect()
+    }
+
+    fn book(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> LocalBook {
+        let side = |levels: &[(&str, &str)]| -> Vec<OrderBookLevel> {
+            levels
+                .iter()
+                .map(|(price, size)| OrderBookLevel {
+                    price: price.parse().unwrap(),
+                    amount: size.parse().unwrap(),
+                })
+                .collect()
+        };
+        LocalBook::from_snapshot(&side(bids), &side(asks))
+    }
+
+    fn message(action: &str, prev_seq_id: i64, seq_id: i64, checksum: Option<i32>) -> String {
+        let mut data = json!({
+            "bids": levels(&[("3366.1", "7"), ("3366", "6")]),
+            "asks": levels(&[("3366.8", "9"), ("3368", "8")]),
+            "ts": "1597026383085",
+            "prevSeqId": prev_seq_id,
+            "seqId": seq_id
+        });
+        if let Some(checksum) = checksum {
+            data["checksum"] = json!(checksum);
+        }
+        json!({
+            "arg": { "channel": "books", "instId": "BTC-USDT" },
+            "action": action,
+            "data": [data]
+        })
+        .to_string()
+    }
+
+    fn connector() -> OkxConnector {
+        OkxConnector::new(Instrument {
+            base: "btc".to_string(),
+            quote: "usdt".to_string(),
+        })
+    }
+
+    fn resubscribed(connector: &mut OkxConnector) -> bool {
+        let ops: Vec<String> = connector
+            .outgoing()
+            .iter()
+            .map(|msg| serde_json::from_str::<Value>(msg).unwrap()["op"].as_str().unwrap().to_string())
+            .collect();
+        match ops.as_slice() {
+            [] => false,
+            [unsubscribe, subscribe] if unsubscribe == "unsubscribe" && subscribe == "subscribe" => true,
+            ops => panic!("unexpected requests {:?}", ops),
+        }
+    }
+
+    #[test]
+    fn checksum_interleaves_the_sides() {
+        // The example of OKX's checksum guide
+        let book = book(&[("3366.1", "7"), ("3366", "6")], &[("3366.8", "9"), ("3368", "8")]);
+        assert_eq!(checksum_string(&book), "3366.1:7:3366.8:9:3366:6:3368:8");
+        assert_eq!(checksum(&book), crc32fast::hash(b"3366.1:7:3366.8:9:3366:6:3368:8") as i32);
+    }
+
+    #[test]
+    fn checksum_carries_on_with_the_longer_side() {
+        // The guide's example of a side with fewer levels
+        let asks = [("3366.8", "9"), ("3368", "8"), ("3372", "8")];
+        assert_eq!(checksum_string(&book(&[("3366.1", "7")], &asks)), "3366.1:7:3366.8:9:3368:8:3372:8");
+        let bids = [("3366.1", "7"), ("3366", "6"), ("3365.5", "2")];
+        assert_eq!(checksum_string(&book(&bids, &[("3366.8", "9")])), "3366.1:7:3366.8:9:3366:6:3365.5:2");
+    }
+
+    #[test]
+    fn checksum_keeps_the_original_strings() {
+        let book = book(&[("0.0100", "1.50"), ("0.009", "20")], &[("0.0110", "0.000100")]);
+        assert_eq!(checksum_string(&book), "0.0100:1.50:0.0110:0.000100:0.009:20");
+    }
+
+    #[test]
+    fn checksum_is_limited_to_25_levels() {
+        let bids: Vec<(String, String)> = (0..30).map(|i| (format!("{}", 100 - i), "1".to_string())).collect();
+        let bids: Vec<(&str, &str)> = bids.iter().map(|(price, size)| (price.as_str(), size.as_str())).collect();
+        let fields = checksum_string(&book(&bids, &[]));
+        assert_eq!(fields.split(':').count(), 2 * CHECKSUM_DEPTH);
+        assert!(fields.ends_with("76:1"));
+    }
+
+    #[tokio::test]
+    async fn book_with_a_matching_checksum_is_published() {
+        let mut connector = connector();
+        let expected = crc32fast::hash(b"3366.1:7:3366.8:9:3366:6:3368:8") as i32;
+        let book = connector.parse(&message("snapshot", -1, 10, Some(expected))).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 10);
+        assert_eq!(book.bids[1].price.to_string(), "3366");
+        assert!(!resubscribed(&mut connector));
+
+        assert!(connector.parse(&message("snapshot", -1, 10, Some(expected + 1))).await.unwrap().is_none());
+        assert!(resubscribed(&mut connector));
+    }
+
+    #[tokio::test]
+    async fn prev_seq_id_gap_resubscribes() {
+        let mut connector = connector();
+        assert!(connector.parse(&message("snapshot", -1, 10, None)).await.unwrap().is_some());
+        assert!(connector.parse(&message("update", 10, 11, None)).await.unwrap().is_some());
+        assert!(!resubscribed(&mut connector));
+
+        // 12 got lost
+        assert!(connector.parse(&message("update", 12, 13, None)).await.unwrap().is_none());
+        assert!(resubscribed(&mut connector));
+        // Updates until the fresh snapshot are dropped, without resubscribing again
+        assert!(connector.parse(&message("update", 13, 14, None)).await.unwrap().is_none());
+        assert!(!resubscribed(&mut connector));
+        let book = connector.parse(&message("snapshot", -1, 20, None)).await.unwrap().unwrap();
+        assert_eq!(book.sequence, 20);
+        assert!(connector.parse(&message("update", 20, 21, None)).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+
+use crate::{
+    catalogue::InstrumentInfo,
+    model::{Decimal, OrderBookLevel},
+};
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxArg {
+    pub channel: String,
+    pub inst_id: String,
+}
+
+/// Responses to `subscribe`/`unsubscribe` requests and errors
+#[derive(Debug, Deserialize)]
+pub struct OkxEvent {
+    pub event: String,
+    pub arg: Option<OkxArg>,
+    pub code: Option<String>,
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OkxBookMessage {
+    /// `snapshot` or `update` on `books`, absent on `books5` where every message is a snapshot
+    pub action: Option<String>,
+    pub data: Vec<OkxBook>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxBook {
+    pub asks: Vec<OkxLevel>,
+    pub bids: Vec<OkxLevel>,
+    pub checksum: Option<i32>,
+    pub prev_seq_id: Option<i64>,
+    pub seq_id: Option<i64>,
+    /// Milliseconds
+    pub ts: Option<String>,
+}
+
+/// `[price, size, deprecated, number of orders]`
+#[derive(Debug, Deserialize, Clone)]
+pub struct OkxLevel {
+    pub price: Decimal,
+    pub size: Decimal,
+    pub liquidated_orders: String,
+    pub orders: String,
+}
+
+impl From<OkxLevel> for OrderBookLevel {
+    fn from(level: OkxLevel) -> Self {
+        Self {
+            price: level.price,
+            amount: level.size,
+        }
+    }
+}
+
+/// `/api/v5/public/instruments` response
+#[derive(Debug, Deserialize)]
+pub struct OkxInstruments {
+    pub data: Vec<OkxInstrument>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxInstrument {
+    pub inst_id: String,
+    pub base_ccy: String,
+    pub quote_ccy: String,
+    pub tick_sz: String,
+    pub lot_sz: String,
+    /// e.g. `live`, `suspend`
+    pub state: String,
+}
+
+impl From<OkxInstrument> for InstrumentInfo {
+    fn from(instrument: OkxInstrument) -> Self {
+        Self {
+            trading: instrument.state == "live",
+            symbol: instrument.inst_id,
+            base: instrument.base_ccy.to_lowercase(),
+            quote: instrument.quote_ccy.to_lowercase(),
+            tick_size: instrument.tick_sz.parse().ok(),
+            lot_size: instrument.lot_sz.parse().ok(),
+            status: instrument.state,
+        }
+    }
+}
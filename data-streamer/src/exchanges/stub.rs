@@ -0,0 +1,66 @@
+//! Local REST endpoint standing in for a venue's snapshots in the connectors' tests
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+/// Answers every request with the next canned JSON body, the last one repeatedly. Stopped when dropped
+pub(crate) struct StubRest {
+    url: String,
+    requests: Arc<AtomicUsize>,
+    task: JoinHandle<()>,
+}
+
+impl StubRest {
+    pub async fn start(bodies: &[String]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let bodies = bodies.to_vec();
+        let served = requests.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                // Requests are header only, read up to the blank line ending them
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buf[..read]),
+                    }
+                }
+                let index = served.fetch_add(1, Ordering::SeqCst);
+                let body = bodies.get(index).or(bodies.last()).cloned().unwrap_or_default();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        Self { url, requests, task }
+    }
+
+    /// Base url to point the connectors at
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Requests answered so far
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for StubRest {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
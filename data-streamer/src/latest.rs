@@ -0,0 +1,120 @@
+use crate::{model::OrderBook, Exchange};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::watch;
+
+/// How the books reach the consumers of a [Streamer](crate::Streamer)
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Every book is queued on [Streamer::stream](crate::Streamer::stream) along with the other events.
+    /// A consumer falling behind the channel's capacity gets `RecvError::Lagged` and misses the oldest events
+    #[default]
+    Queued,
+    /// Only the latest book of every `(Exchange, instrument)` is kept, read through
+    /// [Streamer::watch_books](crate::Streamer::watch_books). A book supersedes the previous one of its
+    /// instrument, so a slow consumer skips the intermediate ones and always gets the freshest.
+    /// The other events are still queued on [Streamer::stream](crate::Streamer::stream)
+    Conflated,
+}
+
+/// A book and the version it was published at
+#[derive(Debug)]
+struct Slot {
+    version: u64,
+    book: OrderBook,
+}
+
+/// Latest book of every `(Exchange, instrument)`. The version counts the books published,
+/// the watchers compare it with the last one they've seen
+#[derive(Debug, Clone)]
+pub(crate) struct LatestBooks {
+    slots: Arc<Mutex<HashMap<(Exchange, String), Slot>>>,
+    version: Arc<watch::Sender<u64>>,
+}
+
+impl LatestBooks {
+    pub fn new() -> Self {
+        Self {
+            slots: Arc::new(Mutex::new(HashMap::new())),
+            version: Arc::new(watch::channel(0).0),
+        }
+    }
+
+    /// Replaces the instrument's book and wakes the watchers
+    pub fn publish(&self, book: OrderBook) {
+        let Ok(mut slots) = self.slots.lock() else {
+            return;
+        };
+        let version = *self.version.borrow() + 1;
+        slots.insert((book.exchange.clone(), book.instrument.clone()), Slot { version, book });
+        // Bumped with the slots locked, a watcher seeing the version finds the book
+        self.version.send_replace(version);
+    }
+
+    pub fn watch(&self) -> BookWatcher {
+        let mut version = self.version.subscribe();
+        let seen = *version.borrow_and_update();
+        BookWatcher {
+            slots: self.slots.clone(),
+            version,
+            seen,
+        }
+    }
+}
+
+/// Reads the latest books of a [Delivery::Conflated] streamer. Every watcher keeps track of what it has seen,
+/// so each consumer gets every instrument's freshest book without queueing the ones in between
+#[derive(Debug)]
+pub struct BookWatcher {
+    slots: Arc<Mutex<HashMap<(Exchange, String), Slot>>>,
+    version: watch::Receiver<u64>,
+    /// Version of the last book returned
+    seen: u64,
+}
+
+impl BookWatcher {
+    /// Waits for books newer than the ones returned so far, and returns the latest book of every
+    /// instrument updated since. `None` once the streamer is gone.
+    /// The slots keep the last book of a venue that went down, see [StreamEvent::Disconnected](crate::StreamEvent)
+    pub async fn changed(&mut self) -> Option<Vec<OrderBook>> {
+        loop {
+            self.version.changed().await.ok()?;
+            let books = self.updated();
+            if !books.is_empty() {
+                return Some(books);
+            }
+        }
+    }
+
+    /// Latest book of every instrument, whether it was returned already or not
+    pub fn books(&self) -> Vec<OrderBook> {
+        match self.slots.lock() {
+            Ok(slots) => slots.values().map(|slot| slot.book.clone()).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Latest book of an instrument, by the [OrderBook::instrument] its books are labelled with
+    pub fn book(&self, exchange: &Exchange, instrument: &str) -> Option<OrderBook> {
+        let slots = self.slots.lock().ok()?;
+        slots
+            .get(&(exchange.clone(), instrument.to_string()))
+            .map(|slot| slot.book.clone())
+    }
+
+    /// Books published after the last seen version, marking them seen
+    fn updated(&mut self) -> Vec<OrderBook> {
+        let Ok(slots) = self.slots.lock() else {
+            return vec![];
+        };
+        let seen = self.seen;
+        self.seen = *self.version.borrow_and_update();
+        slots
+            .values()
+            .filter(|slot| slot.version > seen)
+            .map(|slot| slot.book.clone())
+            .collect()
+    }
+}
@@ -1,11 +1,22 @@
+pub mod book;
+mod catalogue;
+pub mod connector;
+mod endpoints;
+mod error;
+mod latest;
 pub mod model;
+mod reconnect;
+mod recorder;
+mod replay;
+mod symbols;
 
-use model::OrderBook;
 use serde_enum_str::{Deserialize_enum_str, Serialize_enum_str};
-use serde_json::{json, Value};
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
 use tokio::sync::{broadcast};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 
 pub type WebSocket = tokio_tungstenite::tungstenite::WebSocket<
     tokio_tungstenite::tungstenite::stream::MaybeTlsStream<std::net::TcpStream>,
@@ -13,10 +24,28 @@ pub type WebSocket = tokio_tungstenite::tungstenite::WebSocket<
 pub type SocketError = tokio_tungstenite::tungstenite::Error;
 
 mod exchanges;
-use exchanges::binance::model::BinanceOrderBook;
-use exchanges::bitstamp::model::BitStampOrderBookWrapper;
+pub use connector::{
+    Connection, ConnectionKind, ExchangeConnector, StreamEvent, Heartbeat, LastUpdates, ParseErrorCounts, VenueStatuses,
+};
+pub use catalogue::{Catalogue, CatalogueFetcher, InstrumentInfo, VenueCatalogue};
+pub use endpoints::{ExchangeEndpoints, VenueEndpoints};
+pub use error::{ConfigError, InitError, StreamerError};
+pub use latest::{BookWatcher, Delivery};
+pub use reconnect::{ReconnectPolicy, VenueStatus};
+pub use recorder::{Direction, FrameKind, Record, RecordEvent, Recorder, RecorderConfig};
+pub use replay::{ReplayExchange, ReplaySpeed};
+pub use symbols::{Substitution, SymbolRegistry, VenueInstrument};
+pub use exchanges::{
+    binance::{BinanceConnector, BinanceDiffConnector, BinanceTradeConnector},
+    bitstamp::{BitstampConnector, BitstampDiffConnector, BitstampTradeConnector},
+    bybit::{BybitCategory, BybitConnector},
+    coinbase::CoinbaseConnector,
+    htx::HtxConnector,
+    kraken::KrakenConnector,
+    kucoin::KucoinConnector,
+    okx::{OkxChannel, OkxConnector},
+};
 
-use futures::{SinkExt, StreamExt};
 use log::*;
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -35,183 +64,389 @@ impl Instrument {
     pub fn get_symbol_slash(&self) -> String {
         format!("{}/{}",self.base, self.quote)
     }
-
-    pub fn get_symbol_compact_usdt(&self) -> String {
-        if self.base.to_lowercase() == "usd" {
-            format!("{}t{}",self.base, self.quote)
-        }
-        else if self.quote.to_lowercase() == "usd" {
-            format!("{}{}t",self.base, self.quote)
-        }
-        else {
-            format!("{}{}",self.base, self.quote)
-        }
-    }
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize_enum_str, Serialize_enum_str)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Deserialize_enum_str, Serialize_enum_str)]
 pub enum Exchange {
     #[default]
     Binance,
     Bitstamp,
+    Kraken,
+    Coinbase,
+    Okx,
+    Bybit,
+    Htx,
+    Kucoin,
+    /// Exchanges implemented outside this crate through [ExchangeConnector]
+    #[serde(other)]
+    Other(String),
+}
+
+/// Which orderbook stream the built-in connectors consume
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookMode {
+    /// Periodic snapshots of the top levels
+    #[default]
+    Snapshot,
+    /// Incremental updates applied to a full depth local book, synchronised with a REST snapshot
+    Diff,
 }
 
 #[derive(Debug)]
 pub struct Streamer {
-    pub stream: broadcast::Receiver<OrderBook>,
+    pub stream: broadcast::Receiver<StreamEvent>,
+    parse_errors: ParseErrorCounts,
+    statuses: VenueStatuses,
+    last_updates: LastUpdates,
+    catalogue: Catalogue,
+    instrument_info: HashMap<(Exchange, String), InstrumentInfo>,
+    latest: Option<latest::LatestBooks>,
+}
+
+impl Streamer {
+    /// A watcher of the latest books when they are [Delivery::Conflated], `None` when they are queued on `stream`.
+    /// Every consumer takes a watcher of its own
+    pub fn watch_books(&self) -> Option<BookWatcher> {
+        self.latest.as_ref().map(|latest| latest.watch())
+    }
+
+    /// Messages that couldn't be parsed and were skipped, per exchange
+    pub fn parse_errors(&self) -> HashMap<Exchange, u64> {
+        self.parse_errors.lock().map(|counts| counts.clone()).unwrap_or_default()
+    }
+
+    /// Circuit breaker state per exchange, see [ReconnectPolicy::failure_threshold]. An exchange is up only when
+    /// all its connections are, one of them down takes it down, see [Streamer::connection_status]
+    pub fn venue_status(&self) -> HashMap<Exchange, VenueStatus> {
+        let mut venues = HashMap::new();
+        for ((exchange, _), status) in self.connection_status() {
+            let venue = venues.entry(exchange).or_insert(status);
+            if status == VenueStatus::Down {
+                *venue = VenueStatus::Down;
+            }
+        }
+        venues
+    }
+
+    /// Circuit breaker state per connection, connections that haven't been healthy yet are missing
+    pub fn connection_status(&self) -> HashMap<(Exchange, Connection), VenueStatus> {
+        self.statuses.lock().map(|statuses| statuses.clone()).unwrap_or_default()
+    }
+
+    /// When the last book update was received, per exchange and instrument
+    pub fn last_updates(&self) -> HashMap<(Exchange, String), Instant> {
+        self.last_updates.lock().map(|last_updates| last_updates.clone()).unwrap_or_default()
+    }
+
+    /// Instruments listed by the streamed exchanges, empty when validation is disabled
+    pub fn catalogue(&self) -> &Catalogue {
+        &self.catalogue
+    }
+
+    /// Tick size, lot size and status of a streamed instrument on an exchange, by the
+    /// [OrderBook::instrument](model::OrderBook::instrument) its books are labelled with
+    pub fn instrument_info(&self, exchange: &Exchange, instrument: &str) -> Option<&InstrumentInfo> {
+        self.instrument_info.get(&(exchange.clone(), instrument.to_string()))
+    }
 }
 
-#[derive(Debug, Clone)]
 pub struct Subscriptions {
-    pub instrument: Instrument,
+    /// Instruments streamed from every exchange
+    pub instruments: Vec<Instrument>,
     pub exchanges: Vec<Exchange>,
+    pub book_mode: BookMode,
+    /// Market the Bybit instruments are streamed from
+    pub bybit_category: BybitCategory,
+    /// Whether the trades are streamed too, as [StreamEvent::Trade]
+    pub trades: bool,
+    /// Reconnect policy of the exchanges missing from `reconnect_policies`
+    pub reconnect_policy: ReconnectPolicy,
+    pub reconnect_policies: HashMap<Exchange, ReconnectPolicy>,
+    /// Connections without a book update for this long are closed and re-established. `None` disables the watchdog
+    pub stale_after: Option<Duration>,
+    /// Maps the instruments to each venue's native symbols
+    pub symbols: SymbolRegistry,
+    /// Fetches the listings the instruments are validated against. `None` disables the validation
+    pub catalogue: Option<CatalogueFetcher>,
+    /// Websocket and REST urls of the exchanges not reached at their production ones
+    pub endpoints: ExchangeEndpoints,
+    /// Records the raw frames of the exchanges it's switched on for
+    pub recorder: Option<Recorder>,
+    /// Plays a recording back instead of connecting to the exchanges
+    pub replay: Option<ReplayExchange>,
+    /// Whether the books are queued with the other events or conflated to the latest per instrument
+    pub delivery: Delivery,
+    /// Events [Streamer::stream] buffers, at least 1. A consumer falling further behind gets `RecvError::Lagged`
+    /// and misses the oldest ones
+    pub capacity: usize,
+    connectors: Vec<Box<dyn ExchangeConnector>>,
+}
+
+impl fmt::Debug for Subscriptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscriptions")
+            .field("instruments", &self.instruments)
+            .field("exchanges", &self.exchanges)
+            .field("book_mode", &self.book_mode)
+            .field("bybit_category", &self.bybit_category)
+            .field("trades", &self.trades)
+            .field("reconnect_policy", &self.reconnect_policy)
+            .field("reconnect_policies", &self.reconnect_policies)
+            .field("stale_after", &self.stale_after)
+            .field("symbols", &self.symbols)
+            .field("catalogue", &self.catalogue)
+            .field("endpoints", &self.endpoints)
+            .field("recorder", &self.recorder)
+            .field("replay", &self.replay)
+            .field("delivery", &self.delivery)
+            .field("capacity", &self.capacity)
+            .field("connectors", &self.connectors.iter().map(|c| c.exchange()).collect::<Vec<_>>())
+            .finish()
+    }
 }
 
 /// [Subscriptions] is the core of `data-streamer`. Spawns a task for each exchange, each task sends updates
-/// from the respective websocket to the channel. The client reads the messages coming from this channel
+/// from the respective websocket to the channel. The client reads the messages coming from this channel,
+/// routing them by [OrderBook::instrument](model::OrderBook::instrument)
 impl Subscriptions {
-    pub async fn init(self) -> Result<Streamer, SocketError> {
-        let (tx, rx) = broadcast::channel::<OrderBook>(16);
-        for exchange in self.exchanges {
-            let tx2 = tx.clone();
-            match exchange {
-                Exchange::Binance => {
-                    tokio::spawn(consume_binance(self.instrument.clone(), tx2));
-                }
-                Exchange::Bitstamp => {
-                    tokio::spawn(consume_bitstamp(self.instrument.clone(), tx2));
-                }
-            }
+    pub fn new(instruments: Vec<Instrument>, exchanges: Vec<Exchange>) -> Self {
+        Self {
+            instruments,
+            exchanges,
+            book_mode: BookMode::default(),
+            bybit_category: BybitCategory::default(),
+            trades: false,
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnect_policies: HashMap::new(),
+            stale_after: Some(Duration::from_secs(30)),
+            symbols: SymbolRegistry::default(),
+            catalogue: Some(CatalogueFetcher::new()),
+            endpoints: ExchangeEndpoints::default(),
+            recorder: None,
+            replay: None,
+            delivery: Delivery::default(),
+            capacity: 16,
+            connectors: vec![],
         }
-        Ok(Streamer { stream: rx })
     }
-}
 
-/// Connects to Binance webscoket stream. Handles reconnection with exponential backoff
-/// Sends messages back to the main channdel from which it's called
-pub async fn consume_binance(instrument: Instrument, sender: broadcast::Sender<OrderBook>) {
-    let mut sleep = 100; //ms
-    loop {
-        let request = format!("wss://stream.binance.com:9443/ws/{}@depth20@100ms", instrument.get_symbol_compact_usdt());
-        let (mut stream, _response) = connect_async(request)
-            .await
-            .expect("Expected connection with Binance to work");
-
-        info!("Initialized Binance stream");
-        while let Some(event) = stream.next().await {
-            match event {
-                Ok(Message::Text(msg)) => {
-                    let raw_ob: BinanceOrderBook = serde_json::from_str(&msg).expect("Can't parse");
-                    let ob = OrderBook::from((Exchange::Binance, instrument.get_symbol_compact(), raw_ob));
-                    let _ = sender.send(ob);
-                    sleep = 100;
-                }
-                Ok(Message::Binary(msg)) => {
-                    warn!("Received binary message from Binance. Msg: {:?}", msg);
-                    sleep = 100;
-                }
-                Ok(Message::Ping(msg)) => {
-                    info!("Received PING message from Binance");
-                    match stream.send(Message::Pong(msg)).await {
-                        Ok(()) => {
-                            info!("Sent PONG message to Binance");
-                            sleep = 100;
-                        },
-                        Err(err) => {
-                            error!("Couldn't send PONG to Binance. Error: {:?}", err);
-                            break;
-                        },
-                    };
-                }
-                Ok(msg) => {
-                    warn!("Received a non-handled message from Binance. Msg: {:?}", msg );
-                    sleep = 100;
-                }
-                Err(err) => {
-                    error!("Error from Binance websocket: {:?}", err);
-                    break;
-                }
-            }
-        }
-        // Exponential backoff
-        warn!("Binance stream disconnected, re-connecting. Sleep:{}", sleep);
-        tokio::time::sleep(Duration::from_millis(sleep)).await;
-        sleep *= 2;
+    pub fn with_book_mode(mut self, book_mode: BookMode) -> Self {
+        self.book_mode = book_mode;
+        self
     }
-}
 
-/// Connects to Bitstamp webscoket stream. Handles reconnection with exponential backoff
-/// Sends messages back to the main channdel from which it's called
-pub async fn consume_bitstamp(instrument: Instrument, sender: broadcast::Sender<OrderBook>){
-    let mut sleep = 100; //ms
-    loop {
-        let (mut ws_stream, _ws_response) = connect_async("wss://ws.bitstamp.net")
-            .await
-            .expect("Expected connection with Bitstamp to work");
-
-        info!("Initialized Bitstamp stream");
-        let _ = ws_stream
-            .send(Message::Text(
-                json!({
-                "event": "bts:subscribe",
-                    "data": {
-                        "channel": format!("order_book_{}", instrument.get_symbol_compact())
-                    }
-                })
-                .to_string(),
-            ))
-            .await;
-
-        info!("Sent subscription message to Bitstamp (required)");
-        while let Some(event) = ws_stream.next().await {
-            match event {
-                Ok(Message::Text(msg)) => {
-                    let resp = serde_json::from_str::<HashMap<String, Value>>(&msg);
-                    if resp.is_err() {
-                        error!("Error from Bitstamp: {:?}", resp);
-                    }
-                    let obj = resp.unwrap();
-                    let event = obj.get("event").unwrap().as_str().unwrap();
-                    match event {
-                        "data" => {
-                            let raw_ob: BitStampOrderBookWrapper = serde_json::from_str(&msg).expect("Can't parse");
-                            let ob = OrderBook::from((Exchange::Bitstamp, instrument.get_symbol_compact(), raw_ob));
-                            let _ = sender.send(ob);
+    /// Streams the Bybit instruments from this market, e.g. the USDT perpetuals. They are validated against
+    /// its listing. A streamer streams a single category, as the books of both would be labelled alike:
+    /// spot and linear at once take a streamer each
+    pub fn with_bybit_category(mut self, bybit_category: BybitCategory) -> Self {
+        self.bybit_category = bybit_category;
+        self
+    }
+
+    /// Streams the trades of the instruments alongside the books, over connections of their own.
+    /// Trades can be minutes apart on quiet pairs, those connections aren't watched for staleness
+    pub fn with_trades(mut self, trades: bool) -> Self {
+        self.trades = trades;
+        self
+    }
+
+    /// Reconnect policy of every exchange without one of its own
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Reconnect policy of a single exchange, overrides [Subscriptions::with_reconnect_policy]
+    pub fn with_exchange_reconnect_policy(mut self, exchange: Exchange, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policies.insert(exchange, policy);
+        self
+    }
+
+    /// Staleness threshold of the feeds, `None` disables the watchdog
+    pub fn with_stale_after(mut self, stale_after: Option<Duration>) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    pub fn with_symbol_registry(mut self, symbols: SymbolRegistry) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn with_catalogue(mut self, catalogue: Option<CatalogueFetcher>) -> Self {
+        self.catalogue = catalogue;
+        self
+    }
+
+    /// Connects the built-in connectors to these endpoints, e.g. a testnet or Binance.US. The catalogue fetches
+    /// the listings from the overridden REST urls, unless it was given urls of its own
+    pub fn with_endpoints(mut self, endpoints: ExchangeEndpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Records the raw websocket frames of the exchanges the recorder is configured for
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Feeds the connectors a recording instead of the live websockets, see [ReplayExchange]
+    pub fn with_replay(mut self, replay: ReplayExchange) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// See [Delivery]
+    pub fn with_delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// Events buffered for the consumer, see [Subscriptions::capacity]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Registers a connector implemented outside this crate. It gets streamed along with the built-in `exchanges`
+    pub fn with_connector(mut self, connector: impl ExchangeConnector + 'static) -> Self {
+        self.connectors.push(Box::new(connector));
+        self
+    }
+
+    /// Spawns the connectors. Fails without spawning any if an exchange doesn't list one of the instruments
+    pub async fn init(self) -> Result<Streamer, InitError> {
+        let (tx, rx) = broadcast::channel::<StreamEvent>(self.capacity.max(1));
+        let outputs = connector::Outputs {
+            events: tx,
+            parse_errors: ParseErrorCounts::default(),
+            statuses: VenueStatuses::default(),
+            last_updates: LastUpdates::default(),
+            latest: (self.delivery == Delivery::Conflated).then(latest::LatestBooks::new),
+        };
+        let driver_config = |exchange: &Exchange,
+                             symbols: HashMap<String, VenueInstrument>,
+                             scales: HashMap<String, (u32, u32)>| connector::DriverConfig {
+            policy: self.reconnect_policies.get(exchange).unwrap_or(&self.reconnect_policy).clone(),
+            stale_after: self.stale_after,
+            symbols,
+            scales,
+            recorder: self.recorder.clone().filter(|recorder| recorder.records(exchange)),
+        };
+        // Connectors registered by hand subscribe to whatever they were built with
+        let mut connectors: Vec<_> = self
+            .connectors
+            .into_iter()
+            .map(|connector| {
+                let config = driver_config(&connector.exchange(), HashMap::new(), HashMap::new());
+                (connector, config)
+            })
+            .collect();
+        let catalogue = match &self.catalogue {
+            Some(fetcher) => {
+                fetcher
+                    .clone()
+                    .with_endpoints(&self.endpoints)
+                    .with_bybit_category(self.bybit_category)
+                    .fetch(&self.exchanges)
+                    .await
+            }
+            None => Catalogue::default(),
+        };
+        let mut instrument_info = HashMap::new();
+        let mut unlisted = vec![];
+        for exchange in &self.exchanges {
+            let venue_instruments: Vec<VenueInstrument> = self
+                .instruments
+                .iter()
+                .map(|instrument| self.symbols.resolve(exchange, instrument))
+                .collect();
+            let mut scales = HashMap::new();
+            if catalogue.instruments(exchange).is_some() {
+                for venue_instrument in &venue_instruments {
+                    match catalogue.get(exchange, &venue_instrument.native) {
+                        Some(info) if info.trading => {
+                            scales.insert(
+                                venue_instrument.native.get_symbol_compact(),
+                                (info.price_scale().unwrap_or(0), info.amount_scale().unwrap_or(0)),
+                            );
+                            instrument_info.insert(
+                                (exchange.clone(), venue_instrument.canonical.get_symbol_compact()),
+                                info.clone(),
+                            );
                         }
-                        "bts:subscription_succeeded" => info!("Connection with Bitstamp succedded"),
-                        _ =>  warn!("Received non-data message from Bitstamp. Msg: {:?}", event),
+                        _ => unlisted.push((exchange.clone(), venue_instrument.canonical.clone())),
                     }
-                    sleep = 100;
                 }
-                Ok(Message::Binary(msg)) => warn!("Received binary message from Bitstamp. Msg: {:?}", msg),
-                Ok(Message::Ping(msg)) => {
-                    info!("Received PING message from Bitstamp");
-                    match ws_stream.send(Message::Pong(msg)).await {
-                        Ok(()) => {
-                            info!("Sent PONG message to Bitstamp");
-                            sleep = 100;
-                        },
-                        Err(err) => {
-                            error!("Couldn't send PONG to Bitstamp. Error: {:?}", err);
-                            break;
-                        },
+            } else if self.catalogue.is_some() {
+                warn!("No instrument listing for {}, its instruments aren't validated", exchange);
+            }
+            for venue_instrument in venue_instruments.iter().filter(|v| !v.substitutions.is_empty()) {
+                info!(
+                    "Streaming {} from {} as {} ({})",
+                    venue_instrument.canonical.get_symbol_slash(),
+                    exchange,
+                    venue_instrument.native.get_symbol_slash(),
+                    venue_instrument
+                        .substitutions
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            let natives: Vec<Instrument> = venue_instruments.iter().map(|v| v.native.clone()).collect();
+            let symbols: HashMap<String, VenueInstrument> = venue_instruments
+                .into_iter()
+                .map(|v| (v.native.get_symbol_compact(), v))
+                .collect();
+            let endpoints = self.endpoints.get(exchange);
+            let built_in =
+                exchanges::connectors_for(exchange, &natives, self.book_mode, &endpoints, self.bybit_category);
+            if built_in.is_empty() {
+                warn!("No built-in connector for {}, register one with `Subscriptions::with_connector`", exchange);
+            }
+            connectors.extend(
+                built_in
+                    .into_iter()
+                    .map(|connector| (connector, driver_config(exchange, symbols.clone(), scales.clone()))),
+            );
+            if self.trades {
+                let trade_connectors = exchanges::trade_connectors_for(exchange, &natives, &endpoints);
+                if trade_connectors.is_empty() {
+                    warn!("No built-in trade connector for {}, its trades aren't streamed", exchange);
+                }
+                connectors.extend(trade_connectors.into_iter().map(|connector| {
+                    let config = connector::DriverConfig {
+                        stale_after: None,
+                        ..driver_config(exchange, symbols.clone(), scales.clone())
                     };
-                    
+                    (connector, config)
+                }));
+            }
+        }
+        if !unlisted.is_empty() {
+            return Err(InitError::Unlisted(unlisted));
+        }
+        match self.replay {
+            Some(replay) => {
+                tokio::spawn(replay::run_replay(replay, connectors, outputs.clone()));
+            }
+            None => {
+                for (connector, config) in connectors {
+                    tokio::spawn(connector::run_connector(connector, config, outputs.clone()));
                 }
-                Ok(msg) => {
-                    warn!("Received a non-handled message from Bitstamp, ignoring. Msg: {:?}", msg);
-                    sleep = 100;
-                },
-                Err(err) => {
-                    error!("Error from Bitstamp websocket: {:?}", err);
-                    break;
-                },
-                
             }
         }
-        // Exponential backoff
-        warn!("Bitstamp stream disconnected, re-connecting. Sleep:{}", sleep);
-        tokio::time::sleep(Duration::from_millis(sleep)).await;
-        sleep *= 2;
+        Ok(Streamer {
+            stream: rx,
+            parse_errors: outputs.parse_errors,
+            statuses: outputs.statuses,
+            last_updates: outputs.last_updates,
+            catalogue,
+            instrument_info,
+            latest: outputs.latest,
+        })
     }
-}
\ No newline at end of file
+}
@@ -1,14 +1,31 @@
 use serde::de;
 use serde::{Deserialize, Deserializer};
 
-use crate::Exchange;
+use crate::{Exchange, Substitution};
+use chrono::{DateTime, Utc};
+pub use rust_decimal::Decimal;
 
-#[derive(Debug, Deserialize, Clone)]
+/// A price level. Prices and amounts are exact decimals, parsed from the venue's text without going through `f64`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct OrderBookLevel {
-    #[serde(deserialize_with = "de_float_from_str")]
-    pub price: f64,
-    #[serde(deserialize_with = "de_float_from_str")]
-    pub amount: f64,
+    pub price: Decimal,
+    pub amount: Decimal,
+}
+
+impl OrderBookLevel {
+    /// Pads the price and amount to at least the given decimal places, e.g. `100.1` to `100.10` for a `0.01`
+    /// tick. Decimals beyond them are kept, the value never changes
+    pub fn pad_scale(&mut self, price_scale: u32, amount_scale: u32) {
+        pad_scale(&mut self.price, price_scale);
+        pad_scale(&mut self.amount, amount_scale);
+    }
+}
+
+/// Pads a decimal to at least `scale` decimal places, without changing its value
+pub(crate) fn pad_scale(value: &mut Decimal, scale: u32) {
+    if value.scale() < scale {
+        value.rescale(scale);
+    }
 }
 
 /// Normalized Orderbook model. Orderbook updates coming from each exchange get transformed
@@ -17,18 +34,53 @@ pub struct OrderBookLevel {
 #[serde(rename_all = "camelCase")]
 pub struct OrderBook {
     pub exchange: Exchange,
+    /// Update id or counter, only comparable between books of the same exchange
     pub sequence: usize,
+    /// When the exchange produced the update, `None` if its messages don't say
+    #[serde(default)]
+    pub exchange_ts: Option<DateTime<Utc>>,
+    /// When the frame carrying the update arrived, stamped by the connection driver before parsing
+    #[serde(default)]
+    pub received_ts: DateTime<Utc>,
     pub instrument: String,
     pub bids: Vec<OrderBookLevel>,
     pub asks: Vec<OrderBookLevel>,
+    /// Assets the venue streamed in place of the requested ones, e.g. `usdt` for `usd`.
+    /// Empty when the book is for the exact instrument requested
+    #[serde(default)]
+    pub substitutions: Vec<Substitution>,
 }
 
-pub fn de_float_from_str<'a, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: Deserializer<'a>,
-{
-    let str_val = String::deserialize(deserializer)?;
-    str_val.parse::<f64>().map_err(de::Error::custom)
+/// Side of the taker of a trade
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// The taker bought, lifting an ask
+    Buy,
+    /// The taker sold, hitting a bid
+    Sell,
+}
+
+/// Normalized trade model. Executions coming from each exchange get transformed into this struct
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Trade {
+    pub exchange: Exchange,
+    pub instrument: String,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub side: Side,
+    /// Id assigned by the exchange, unique per instrument
+    pub trade_id: String,
+    /// When the exchange executed the trade, `None` if its messages don't say
+    #[serde(default)]
+    pub exchange_ts: Option<DateTime<Utc>>,
+    /// When the frame carrying the trade arrived, stamped by the connection driver before parsing
+    #[serde(default)]
+    pub received_ts: DateTime<Utc>,
+    /// Assets the venue streamed in place of the requested ones, see [OrderBook::substitutions]
+    #[serde(default)]
+    pub substitutions: Vec<Substitution>,
 }
 
 pub fn de_usize_from_str<'a, D>(deserializer: D) -> Result<usize, D::Error>
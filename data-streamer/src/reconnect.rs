@@ -0,0 +1,146 @@
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// How a connector reconnects after its connection fails or gets closed.
+/// The delay between attempts grows by `multiplier` up to `max_delay`, each delay is randomised by `jitter`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnection attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between attempts
+    pub max_delay: Duration,
+    /// Factor applied to the delay after every failed attempt
+    pub multiplier: f64,
+    /// Fraction of the delay added or removed at random, e.g. `0.2` waits between 80% and 120% of it
+    pub jitter: f64,
+    /// Consecutive failures after which the connector gives up. `None` retries forever
+    pub max_attempts: Option<u32>,
+    /// How long a connection has to stay up before the backoff is reset
+    pub reset_after: Duration,
+    /// Consecutive failures after which the venue is reported as [VenueStatus::Down]
+    pub failure_threshold: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+            reset_after: Duration::from_secs(10),
+            failure_threshold: 5,
+        }
+    }
+}
+
+/// Circuit breaker state of a venue, or of one of its connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VenueStatus {
+    /// The connection has been healthy for [ReconnectPolicy::reset_after]
+    Up,
+    /// The venue failed [ReconnectPolicy::failure_threshold] times in a row, its data shouldn't be relied on
+    Down,
+}
+
+/// Tracks the reconnection attempts of a single connector
+#[derive(Debug, Clone)]
+pub(crate) struct Backoff {
+    policy: ReconnectPolicy,
+    failures: u32,
+    delay: Duration,
+    connected_at: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            delay: policy.initial_delay,
+            policy,
+            failures: 0,
+            connected_at: None,
+        }
+    }
+
+    pub fn connected(&mut self) {
+        self.connected_at = Some(Instant::now());
+    }
+
+    /// Called on every message. Returns `true` once per connection, when it has been up for
+    /// [ReconnectPolicy::reset_after] and the backoff got reset
+    pub fn received(&mut self) -> bool {
+        match self.connected_at {
+            Some(connected_at) if connected_at.elapsed() >= self.policy.reset_after => {
+                self.connected_at = None;
+                self.failures = 0;
+                self.delay = self.policy.initial_delay;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Registers a failed or ended connection. Returns the delay before the next attempt,
+    /// `None` once [ReconnectPolicy::max_attempts] is exhausted
+    pub fn failed(&mut self) -> Option<Duration> {
+        self.connected_at = None;
+        self.failures += 1;
+        if matches!(self.policy.max_attempts, Some(max_attempts) if self.failures > max_attempts) {
+            return None;
+        }
+        let jitter = self.policy.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            1.0 + rand::thread_rng().gen_range(-jitter..=jitter)
+        } else {
+            1.0
+        };
+        let delay = self.delay.mul_f64(factor).min(self.policy.max_delay);
+        self.delay = self.delay.mul_f64(self.policy.multiplier.max(1.0)).min(self.policy.max_delay);
+        Some(delay)
+    }
+
+    /// Whether the consecutive failures just reached [ReconnectPolicy::failure_threshold]
+    pub fn tripped(&self) -> bool {
+        self.failures == self.policy.failure_threshold
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+/// REST snapshots taking longer are abandoned, the connection's messages wait for them meanwhile
+pub(crate) const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Paces the REST snapshot requests synchronising a diff stream. A snapshot that couldn't be fetched or used
+/// is requested again after a growing delay, rather than on every event of the stream
+#[derive(Debug, Clone)]
+pub(crate) struct SnapshotRetry {
+    backoff: Backoff,
+    next_attempt: Option<Instant>,
+}
+
+impl Default for SnapshotRetry {
+    fn default() -> Self {
+        Self {
+            backoff: Backoff::new(ReconnectPolicy {
+                initial_delay: Duration::from_millis(500),
+                ..ReconnectPolicy::default()
+            }),
+            next_attempt: None,
+        }
+    }
+}
+
+impl SnapshotRetry {
+    /// Whether the snapshot may be requested now
+    pub fn due(&self) -> bool {
+        self.next_attempt.is_none_or(|next_attempt| Instant::now() >= next_attempt)
+    }
+
+    /// The snapshot couldn't be fetched or used, the next request waits for the backoff
+    pub fn failed(&mut self) {
+        self.next_attempt = self.backoff.failed().map(|delay| Instant::now() + delay);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::Exchange;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Received from the exchange
+    In,
+    /// Sent to the exchange
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameKind {
+    Text,
+    Binary,
+    Ping,
+    Pong,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecordEvent {
+    /// The connection to `endpoint` got established
+    Connected { endpoint: String },
+    /// A websocket frame. Text is kept as is, binary payloads are base64 encoded
+    /// and a close frame carries `<code> <reason>`, empty when the exchange didn't send one
+    Frame {
+        direction: Direction,
+        kind: FrameKind,
+        data: String,
+    },
+    /// The connection was lost or closed
+    Disconnected { reason: String },
+}
+
+impl RecordEvent {
+    fn frame(direction: Direction, msg: &Message) -> Self {
+        let (kind, data) = match msg {
+            Message::Text(text) => (FrameKind::Text, text.clone()),
+            Message::Binary(data) => (FrameKind::Binary, BASE64.encode(data)),
+            Message::Ping(data) => (FrameKind::Ping, BASE64.encode(data)),
+            Message::Pong(data) => (FrameKind::Pong, BASE64.encode(data)),
+            Message::Close(frame) => (
+                FrameKind::Close,
+                frame
+                    .as_ref()
+                    .map(|frame| format!("{} {}", u16::from(frame.code), frame.reason))
+                    .unwrap_or_default(),
+            ),
+            Message::Frame(frame) => (FrameKind::Binary, BASE64.encode(frame.payload())),
+        };
+        Self::Frame { direction, kind, data }
+    }
+}
+
+/// A line of a recording file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// When the frame was received or sent
+    pub ts: DateTime<Utc>,
+    pub exchange: Exchange,
+    /// [Recorder] the record comes from, named after the time it started. Empty in the recordings made before
+    /// sessions were recorded, which are told apart by their file
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub session: String,
+    /// Numbers the connections of a recording session, every reconnection gets a new one
+    pub connection: u64,
+    #[serde(flatten)]
+    pub event: RecordEvent,
+}
+
+/// Where and what a [Recorder] records
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Directory the recording files are written to, created if missing
+    pub dir: PathBuf,
+    /// Size a file grows to before the recording moves on to a new one
+    pub max_file_bytes: u64,
+    /// Exchanges recorded, `None` records all of them
+    pub exchanges: Option<HashSet<Exchange>>,
+}
+
+impl RecorderConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_file_bytes: 100 * 1024 * 1024,
+            exchanges: None,
+        }
+    }
+
+    pub fn with_max_file_bytes(mut self, max_file_bytes: u64) -> Self {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    /// Records only these exchanges
+    pub fn with_exchanges(mut self, exchanges: impl IntoIterator<Item = Exchange>) -> Self {
+        self.exchanges = Some(exchanges.into_iter().collect());
+        self
+    }
+}
+
+/// Writes every raw websocket frame of the recorded exchanges, along with the connections being
+/// established and lost, as NDJSON [Record]s. Files are named after the time they were started at
+/// and rotated once they reach [RecorderConfig::max_file_bytes]. Writing happens on a thread of its own,
+/// the connections only queue the records
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    records: mpsc::Sender<Record>,
+    exchanges: Option<HashSet<Exchange>>,
+    /// Recording session, the connections are numbered within it
+    session: String,
+    connections: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    /// Starts the writer thread. Fails if the directory can't be created
+    pub fn start(config: RecorderConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let (records, receiver) = mpsc::channel();
+        let exchanges = config.exchanges.clone();
+        std::thread::Builder::new()
+            .name("data-streamer-recorder".to_string())
+            .spawn(move || write_records(config, receiver))?;
+        Ok(Self {
+            records,
+            exchanges,
+            session: Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string(),
+            connections: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Whether the exchange's connections get recorded
+    pub fn records(&self, exchange: &Exchange) -> bool {
+        self.exchanges.as_ref().is_none_or(|exchanges| exchanges.contains(exchange))
+    }
+
+    /// Starts recording a new connection of the exchange
+    pub(crate) fn connection(&self, exchange: Exchange, endpoint: &str) -> ConnectionRecorder {
+        let recorder = ConnectionRecorder {
+            recorder: self.clone(),
+            exchange,
+            connection: self.connections.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        recorder.record(
+            Utc::now(),
+            RecordEvent::Connected {
+                endpoint: endpoint.to_string(),
+            },
+        );
+        recorder
+    }
+}
+
+/// Records the frames of one connection
+#[derive(Debug)]
+pub(crate) struct ConnectionRecorder {
+    recorder: Recorder,
+    exchange: Exchange,
+    connection: u64,
+}
+
+impl ConnectionRecorder {
+    pub fn received(&self, ts: DateTime<Utc>, msg: &Message) {
+        self.record(ts, RecordEvent::frame(Direction::In, msg));
+    }
+
+    pub fn sent(&self, msg: &Message) {
+        self.record(Utc::now(), RecordEvent::frame(Direction::Out, msg));
+    }
+
+    pub fn disconnected(&self, reason: &str) {
+        self.record(
+            Utc::now(),
+            RecordEvent::Disconnected {
+                reason: reason.to_string(),
+            },
+        );
+    }
+
+    fn record(&self, ts: DateTime<Utc>, event: RecordEvent) {
+        // Fails only once the writer thread is gone, it already logged why
+        let _ = self.recorder.records.send(Record {
+            ts,
+            exchange: self.exchange.clone(),
+            session: self.recorder.session.clone(),
+            connection: self.connection,
+            event,
+        });
+    }
+}
+
+/// A recording file and how much has been written to it
+struct RecordingFile {
+    writer: BufWriter<File>,
+    written: u64,
+}
+
+fn open_file(config: &RecorderConfig) -> io::Result<RecordingFile> {
+    let path = config
+        .dir
+        .join(format!("recording-{}.ndjson", Utc::now().format("%Y%m%dT%H%M%S%.6fZ")));
+    info!("Recording to {:?}", path);
+    Ok(RecordingFile {
+        writer: BufWriter::new(File::create(path)?),
+        written: 0,
+    })
+}
+
+/// Writer thread loop. Flushes whenever it caught up with the queue, so a crash loses little
+fn write_records(config: RecorderConfig, receiver: mpsc::Receiver<Record>) {
+    let mut file: Option<RecordingFile> = None;
+    loop {
+        let record = match receiver.try_recv() {
+            Ok(record) => record,
+            Err(mpsc::TryRecvError::Empty) => {
+                if let Some(file) = file.as_mut() {
+                    if let Err(err) = file.writer.flush() {
+                        error!("Couldn't flush the recording. Error: {}", err);
+                    }
+                }
+                match receiver.recv() {
+                    Ok(record) => record,
+                    Err(_) => return,
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => break,
+        };
+        let mut line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Couldn't serialize a record. Error: {}", err);
+                continue;
+            }
+        };
+        line.push('\n');
+        if let Some(current) = &file {
+            if current.written > 0 && current.written + line.len() as u64 > config.max_file_bytes {
+                file = None;
+            }
+        }
+        if file.is_none() {
+            match open_file(&config) {
+                Ok(opened) => file = Some(opened),
+                Err(err) => {
+                    error!("Couldn't open a recording file in {:?}, dropping a record. Error: {}", config.dir, err);
+                    continue;
+                }
+            }
+        }
+        let Some(current) = file.as_mut() else {
+            continue;
+        };
+        match current.writer.write_all(line.as_bytes()) {
+            Ok(()) => current.written += line.len() as u64,
+            Err(err) => {
+                error!("Couldn't write to the recording, starting a new file. Error: {}", err);
+                file = None;
+            }
+        }
+    }
+    if let Some(mut file) = file {
+        let _ = file.writer.flush();
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    connector::{self, DriverConfig, ExchangeConnector, Outputs, StreamEvent},
+    recorder::{Direction, FrameKind, Record, RecordEvent},
+    StreamerError,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use log::*;
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader},
+    time::Instant,
+};
+
+/// How fast a [ReplayExchange] plays a recording back
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplaySpeed {
+    /// Frames are spaced as they were received
+    #[default]
+    RealTime,
+    /// The spacing of the frames is divided by the factor, e.g. 10 or 100
+    Accelerated(f64),
+    /// Frames are fed one after the other, without waiting
+    Max,
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    /// `realtime`, `max` or the acceleration factor, e.g. `x10`
+    fn from_str(speed: &str) -> Result<Self, Self::Err> {
+        match speed {
+            "realtime" => Ok(ReplaySpeed::RealTime),
+            "max" => Ok(ReplaySpeed::Max),
+            factor => factor
+                .strip_prefix('x')
+                .and_then(|factor| factor.parse::<f64>().ok())
+                .filter(|factor| *factor > 0.0)
+                .map(ReplaySpeed::Accelerated)
+                .ok_or_else(|| format!("invalid replay speed {}, expected realtime, max or e.g. x10", speed)),
+        }
+    }
+}
+
+/// Plays back recordings of the [Recorder](crate::Recorder) in place of the live websockets. The received frames
+/// go through the same connectors as live ones, see [Subscriptions::with_replay](crate::Subscriptions::with_replay).
+/// Every recorded connection is matched to the connector with the same endpoint, or the same subscription
+/// messages when connectors share an endpoint. The connections and the pacing are scoped to the recording
+/// session, so recordings of several runs can be played one after the other. Connectors synchronising with
+/// REST snapshots, i.e. [BookMode::Diff](crate::BookMode::Diff), still fetch them live as those aren't recorded
+#[derive(Debug, Clone)]
+pub struct ReplayExchange {
+    files: Vec<PathBuf>,
+    speed: ReplaySpeed,
+}
+
+impl ReplayExchange {
+    /// Replays `path`, a recording file or a directory of them. Files are played in name order,
+    /// the order they were recorded in
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            files.retain(|file| file.extension().is_some_and(|extension| extension == "ndjson"));
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        Ok(Self {
+            files,
+            speed: ReplaySpeed::default(),
+        })
+    }
+
+    pub fn with_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Recording files, in the order they are played
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Waits until the frame recorded at `ts` is due. `clock` is when the first frame was recorded and played
+    async fn pace(&self, clock: &mut Option<(DateTime<Utc>, Instant)>, ts: DateTime<Utc>) {
+        let factor = match self.speed {
+            ReplaySpeed::RealTime => 1.0,
+            ReplaySpeed::Accelerated(factor) if factor > 0.0 => factor,
+            _ => return,
+        };
+        let (first_ts, started) = *clock.get_or_insert((ts, Instant::now()));
+        let offset = (ts - first_ts).to_std().unwrap_or_default();
+        tokio::time::sleep_until(started + offset.div_f64(factor)).await;
+    }
+}
+
+/// Recorded connections of a recording session being replayed. Every [Recorder](crate::Recorder) numbers its
+/// connections from 1 and has a clock of its own
+#[derive(Debug, Default)]
+struct Session {
+    /// Session of the records, or the file of those that don't tell theirs
+    id: String,
+    /// Connector of each recorded connection
+    connections: HashMap<u64, usize>,
+    /// Connections sharing an endpoint with other connectors, matched on the first subscription message
+    pending: HashMap<u64, Vec<usize>>,
+    /// When the first frame of the session was recorded and played
+    clock: Option<(DateTime<Utc>, Instant)>,
+}
+
+/// Feeds the recorded frames of the connectors' exchanges to them, in the order they were recorded.
+/// Books and trades are stamped with the time their frame was recorded at
+pub(crate) async fn run_replay(
+    replay: ReplayExchange,
+    mut connectors: Vec<(Box<dyn ExchangeConnector>, DriverConfig)>,
+    outputs: Outputs,
+) {
+    let mut session = Session::default();
+    for path in replay.files() {
+        info!("Replaying {:?}", path);
+        let file = match File::open(path).await {
+            Ok(file) => file,
+            Err(err) => {
+                error!("Couldn't open recording {:?}. Error: {}", path, err);
+                continue;
+            }
+        };
+        let mut lines = BufReader::new(file).lines();
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(err) => {
+                    error!("Couldn't read recording {:?}. Error: {}", path, err);
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = match serde_json::from_str::<Record>(&line) {
+                Ok(record) => record,
+                Err(err) => {
+                    warn!("Skipping a malformed record of {:?}. Error: {}", path, err);
+                    continue;
+                }
+            };
+            let candidates: Vec<usize> = connectors
+                .iter()
+                .enumerate()
+                .filter(|(_, (connector, _))| connector.exchange() == record.exchange)
+                .map(|(index, _)| index)
+                .collect();
+            if candidates.is_empty() {
+                continue;
+            }
+            let id = if record.session.is_empty() {
+                path.to_string_lossy().to_string()
+            } else {
+                record.session.clone()
+            };
+            if id != session.id {
+                // The connections still open when the previous session stopped recording are gone
+                for (_, index) in session.connections.drain() {
+                    let (connector, config) = &connectors[index];
+                    outputs.send(StreamEvent::Disconnected {
+                        exchange: connector.exchange(),
+                        connection: config.connection(connector.as_ref()),
+                        reason: "recording session ended".to_string(),
+                    });
+                }
+                session = Session {
+                    id,
+                    ..Session::default()
+                };
+            }
+            let Session {
+                connections,
+                pending,
+                clock,
+                ..
+            } = &mut session;
+            replay.pace(clock, record.ts).await;
+            match record.event {
+                RecordEvent::Connected { endpoint } => {
+                    let same_endpoint: Vec<usize> = candidates
+                        .iter()
+                        .copied()
+                        .filter(|index| connectors[*index].0.endpoint() == endpoint)
+                        .collect();
+                    let candidates = if same_endpoint.is_empty() { candidates } else { same_endpoint };
+                    if let [index] = candidates[..] {
+                        let (connector, config) = &mut connectors[index];
+                        connector.reset();
+                        connections.insert(record.connection, index);
+                        outputs.send(StreamEvent::Connected {
+                            exchange: record.exchange,
+                            connection: config.connection(connector.as_ref()),
+                        });
+                    } else {
+                        pending.insert(record.connection, candidates);
+                    }
+                }
+                RecordEvent::Frame {
+                    direction: Direction::Out,
+                    kind: FrameKind::Text,
+                    data,
+                } => {
+                    let Some(candidates) = pending.get(&record.connection) else {
+                        continue;
+                    };
+                    let matching = candidates
+                        .iter()
+                        .copied()
+                        .find(|index| connectors[*index].0.subscribe_messages().contains(&data));
+                    if let Some(index) = matching {
+                        pending.remove(&record.connection);
+                        let (connector, config) = &mut connectors[index];
+                        connector.reset();
+                        connections.insert(record.connection, index);
+                        outputs.send(StreamEvent::Connected {
+                            exchange: record.exchange,
+                            connection: config.connection(connector.as_ref()),
+                        });
+                    }
+                }
+                RecordEvent::Frame {
+                    direction: Direction::In,
+                    kind,
+                    data,
+                } => {
+                    let Some(index) = connections.get(&record.connection) else {
+                        continue;
+                    };
+                    let (connector, config) = &mut connectors[*index];
+                    let text = match kind {
+                        FrameKind::Text => data,
+                        FrameKind::Binary => {
+                            let decoded = BASE64
+                                .decode(&data)
+                                .map_err(|err| StreamerError::parse(record.exchange.clone(), err, &data))
+                                .and_then(|binary| connector.decode_binary(&binary));
+                            match decoded {
+                                Ok(Some(text)) => text,
+                                Ok(None) => continue,
+                                Err(err) => {
+                                    outputs.error(err);
+                                    continue;
+                                }
+                            }
+                        }
+                        FrameKind::Ping | FrameKind::Pong | FrameKind::Close => continue,
+                    };
+                    if let Err(err) = connector::publish(connector.as_mut(), &text, record.ts, config, &outputs).await {
+                        outputs.error(err);
+                    }
+                    // Nothing to send them to, the recording has what the exchange answered live
+                    connector.outgoing();
+                }
+                RecordEvent::Frame { .. } => {}
+                RecordEvent::Disconnected { reason } => {
+                    pending.remove(&record.connection);
+                    // Connections no connector took up didn't announce themselves either
+                    let Some(index) = connections.remove(&record.connection) else {
+                        continue;
+                    };
+                    let (connector, config) = &connectors[index];
+                    outputs.send(StreamEvent::Disconnected {
+                        exchange: record.exchange,
+                        connection: config.connection(connector.as_ref()),
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+    info!("Replay finished");
+}
@@ -0,0 +1,117 @@
+use crate::{ConfigError, Exchange, Instrument};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, path::Path};
+
+/// An asset traded under a different code on a venue, e.g. `usd` streamed as `usdt` from Binance.
+/// Substitutions applied to a book are recorded on it, see [OrderBook::substitutions](crate::model::OrderBook)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Substitution {
+    pub from: String,
+    pub to: String,
+}
+
+impl fmt::Display for Substitution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}->{}", self.from, self.to)
+    }
+}
+
+/// A canonical instrument as listed on a venue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VenueInstrument {
+    /// The instrument as requested, books are labelled with it
+    pub canonical: Instrument,
+    /// The instrument the venue is subscribed to
+    pub native: Instrument,
+    pub substitutions: Vec<Substitution>,
+}
+
+/// Maps canonical instruments to each venue's native symbols through per exchange substitution rules.
+/// Loadable from a TOML file, e.g.
+/// ```toml
+/// [substitutions]
+/// Binance = [{ from = "usd", to = "usdt" }]
+/// Kraken = [{ from = "btc", to = "xbt" }]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolRegistry {
+    #[serde(default)]
+    pub substitutions: HashMap<Exchange, Vec<Substitution>>,
+}
+
+impl Default for SymbolRegistry {
+    /// The built-in rules: tether in place of the dollar where venues mostly list tether pairs,
+    /// and Kraken's `xbt` for bitcoin
+    fn default() -> Self {
+        let usdt = || {
+            vec![Substitution {
+                from: "usd".to_string(),
+                to: "usdt".to_string(),
+            }]
+        };
+        Self {
+            substitutions: HashMap::from([
+                (Exchange::Binance, usdt()),
+                (Exchange::Okx, usdt()),
+                (Exchange::Bybit, usdt()),
+                (Exchange::Htx, usdt()),
+                (Exchange::Kucoin, usdt()),
+                (
+                    Exchange::Kraken,
+                    vec![Substitution {
+                        from: "btc".to_string(),
+                        to: "xbt".to_string(),
+                    }],
+                ),
+            ]),
+        }
+    }
+}
+
+impl SymbolRegistry {
+    /// Registry without any substitution, every venue gets subscribed to the canonical instrument
+    pub fn empty() -> Self {
+        Self {
+            substitutions: HashMap::new(),
+        }
+    }
+
+    /// The built-in rules overridden by the ones in the TOML file. An exchange listed in the file
+    /// replaces its built-in rules, an empty list disables them
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let file: SymbolRegistry = toml::from_str(&std::fs::read_to_string(path)?)?;
+        let mut registry = Self::default();
+        registry.substitutions.extend(file.substitutions);
+        Ok(registry)
+    }
+
+    pub fn with_substitution(mut self, exchange: Exchange, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.substitutions.entry(exchange).or_default().push(Substitution {
+            from: from.into(),
+            to: to.into(),
+        });
+        self
+    }
+
+    /// The instrument to subscribe to on the exchange and the substitutions that led to it
+    pub fn resolve(&self, exchange: &Exchange, instrument: &Instrument) -> VenueInstrument {
+        let rules = self.substitutions.get(exchange).map(Vec::as_slice).unwrap_or_default();
+        let mut substitutions = vec![];
+        let mut substitute = |asset: &str| match rules.iter().find(|rule| rule.from.eq_ignore_ascii_case(asset)) {
+            Some(rule) => {
+                substitutions.push(rule.clone());
+                rule.to.clone()
+            }
+            None => asset.to_string(),
+        };
+        let native = Instrument {
+            base: substitute(&instrument.base),
+            quote: substitute(&instrument.quote),
+        };
+        VenueInstrument {
+            canonical: instrument.clone(),
+            native,
+            substitutions,
+        }
+    }
+}
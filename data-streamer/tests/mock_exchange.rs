@@ -0,0 +1,145 @@
+//! The streamer against the local stand-ins of the exchanges
+use data_streamer::{
+    BinanceConnector, BitstampConnector, Exchange, Instrument, StreamEvent, Streamer, Subscriptions,
+};
+use mock_exchange::{MockExchange, MockServer, Script, Venue};
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+fn instrument(base: &str, quote: &str) -> Instrument {
+    Instrument {
+        base: base.to_string(),
+        quote: quote.to_string(),
+    }
+}
+
+async fn binance(script: Script) -> (MockServer, Streamer) {
+    let server = MockExchange::new(Venue::Binance).with_script(script).start().await.unwrap();
+    let streamer = Subscriptions::new(vec![], vec![])
+        .with_catalogue(None)
+        .with_connector(BinanceConnector::new(vec![instrument("btc", "usdt")]).with_ws_url(server.ws_url()))
+        .init()
+        .await
+        .unwrap();
+    (server, streamer)
+}
+
+/// Events until the first one matching, fails the test if it doesn't come
+async fn until(streamer: &mut Streamer, matches: impl Fn(&StreamEvent) -> bool) -> Vec<StreamEvent> {
+    let mut events = vec![];
+    timeout(Duration::from_secs(5), async {
+        loop {
+            let event = streamer.stream.recv().await.unwrap();
+            let done = matches(&event);
+            events.push(event);
+            if done {
+                return;
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out, received {:?}", events));
+    events
+}
+
+fn is_book(event: &StreamEvent) -> bool {
+    matches!(event, StreamEvent::Book(_))
+}
+
+#[tokio::test]
+async fn book_is_parsed() {
+    let script = Script::new().book("btcusdt", &[("20000.10", "1.5"), ("20000.00", "2")], &[("20000.20", "0.5")]);
+    let (_server, mut streamer) = binance(script).await;
+    let events = until(&mut streamer, is_book).await;
+    let Some(StreamEvent::Book(book)) = events.last() else {
+        unreachable!()
+    };
+    assert_eq!(book.exchange, Exchange::Binance);
+    assert_eq!(book.instrument, "btcusdt");
+    let bids: Vec<String> = book.bids.iter().map(|level| format!("{}:{}", level.price, level.amount)).collect();
+    assert_eq!(bids, ["20000.10:1.5", "20000.00:2"]);
+    assert_eq!(book.asks[0].price.to_string(), "20000.20");
+}
+
+#[tokio::test]
+async fn malformed_frame_is_counted() {
+    let script = Script::new().malformed().book("btcusdt", &[("100", "1")], &[("101", "1")]);
+    let (_server, mut streamer) = binance(script).await;
+    // Frames are parsed in order, the malformed one is counted by the time the book comes
+    until(&mut streamer, is_book).await;
+    assert_eq!(streamer.parse_errors().get(&Exchange::Binance), Some(&1));
+}
+
+#[tokio::test]
+async fn ping_is_answered() {
+    let script = Script::new().ping().book("btcusdt", &[("100", "1")], &[("101", "1")]);
+    let (server, mut streamer) = binance(script).await;
+    until(&mut streamer, is_book).await;
+    timeout(Duration::from_secs(5), async {
+        while server.activity().pongs == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The ping wasn't answered");
+}
+
+#[tokio::test]
+async fn reconnects_after_a_disconnect() {
+    let server = MockExchange::new(Venue::Binance)
+        .with_script(Script::new().book("btcusdt", &[("100", "1")], &[("101", "1")]).disconnect())
+        .with_script(Script::new().book("btcusdt", &[("102", "1")], &[("103", "1")]))
+        .start()
+        .await
+        .unwrap();
+    let mut streamer = Subscriptions::new(vec![], vec![])
+        .with_catalogue(None)
+        .with_connector(BinanceConnector::new(vec![instrument("btc", "usdt")]).with_ws_url(server.ws_url()))
+        .init()
+        .await
+        .unwrap();
+
+    until(&mut streamer, is_book).await;
+    let events = until(&mut streamer, |event| match event {
+        StreamEvent::Book(book) => book.bids[0].price.to_string() == "102",
+        _ => false,
+    })
+    .await;
+    assert!(events.iter().any(|event| matches!(event, StreamEvent::Disconnected { .. })));
+    assert!(events.iter().any(|event| matches!(event, StreamEvent::Reconnecting { attempt: 1, .. })));
+    assert!(events.iter().any(|event| matches!(event, StreamEvent::Connected { .. })));
+    assert_eq!(server.activity().connections, 2);
+}
+
+#[tokio::test]
+async fn delayed_bitstamp_ack_is_subscribed() {
+    let delay = Duration::from_millis(500);
+    let server = MockExchange::new(Venue::Bitstamp)
+        .with_script(
+            Script::new()
+                .with_subscription_delay(delay)
+                .book("btcusd", &[("30000", "1")], &[("30001", "1")]),
+        )
+        .start()
+        .await
+        .unwrap();
+    let started = Instant::now();
+    let mut streamer = Subscriptions::new(vec![], vec![])
+        .with_catalogue(None)
+        .with_connector(BitstampConnector::new(vec![instrument("btc", "usd")]).with_ws_url(server.ws_url()))
+        .init()
+        .await
+        .unwrap();
+
+    let events = until(&mut streamer, |event| matches!(event, StreamEvent::Subscribed { .. })).await;
+    assert!(started.elapsed() >= delay);
+    let Some(StreamEvent::Subscribed { exchange, channels }) = events.last() else {
+        unreachable!()
+    };
+    assert_eq!(*exchange, Exchange::Bitstamp);
+    assert_eq!(channels, &["order_book_btcusd"]);
+    // Books only come once subscribed
+    assert!(!events.iter().any(is_book));
+    until(&mut streamer, is_book).await;
+    assert!(server.activity().received[0].contains("bts:subscribe"));
+}
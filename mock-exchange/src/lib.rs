@@ -0,0 +1,8 @@
+//! Local websocket servers speaking the Binance and Bitstamp protocols, to run the streamer
+//! against in tests without reaching the exchanges. Point the connectors at [MockServer::ws_url],
+//! e.g. with `BinanceConnector::with_ws_url`
+mod script;
+mod server;
+
+pub use script::{Level, Script, Step};
+pub use server::{Activity, MockExchange, MockServer, Venue};
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+/// A price level as the venues send it, price and amount strings
+pub type Level = (String, String);
+
+/// What a connection of a [MockExchange](crate::MockExchange) does next
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Sends a book snapshot of `symbol`, e.g. `btcusdt` or `btcusd`, in the venue's format
+    Book {
+        symbol: String,
+        bids: Vec<Level>,
+        asks: Vec<Level>,
+    },
+    /// Sends a text frame as is, e.g. a malformed or unexpected message
+    Text(String),
+    Binary(Vec<u8>),
+    /// Sends a ping, the client is expected to answer with a pong
+    Ping(Vec<u8>),
+    /// Pauses the script
+    Wait(Duration),
+    /// Sends a close frame and ends the connection
+    Close,
+    /// Drops the connection without a close frame, as a network failure would
+    Disconnect,
+}
+
+/// Steps a connection plays, in order. The connection stays open once they're done, until a step ends it
+/// or the client leaves. On Bitstamp the steps start with the first acknowledged subscription
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    steps: Vec<Step>,
+    subscription_delay: Duration,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays the acknowledgement of every `bts:subscribe`, Bitstamp only
+    pub fn with_subscription_delay(mut self, subscription_delay: Duration) -> Self {
+        self.subscription_delay = subscription_delay;
+        self
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Book snapshot out of `(price, amount)` pairs, e.g. `("20000.10", "1.5")`
+    pub fn book(self, symbol: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> Self {
+        let levels = |levels: &[(&str, &str)]| {
+            levels
+                .iter()
+                .map(|(price, amount)| (price.to_string(), amount.to_string()))
+                .collect()
+        };
+        self.step(Step::Book {
+            symbol: symbol.to_string(),
+            bids: levels(bids),
+            asks: levels(asks),
+        })
+    }
+
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.step(Step::Text(text.into()))
+    }
+
+    /// A truncated JSON frame, which no connector can parse
+    pub fn malformed(self) -> Self {
+        self.text(r#"{"stream":"btcusdt@depth20@100ms","data":{"lastUpdateId":"#)
+    }
+
+    pub fn ping(self) -> Self {
+        self.step(Step::Ping(vec![]))
+    }
+
+    pub fn wait(self, duration: Duration) -> Self {
+        self.step(Step::Wait(duration))
+    }
+
+    pub fn close(self) -> Self {
+        self.step(Step::Close)
+    }
+
+    pub fn disconnect(self) -> Self {
+        self.step(Step::Disconnect)
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn subscription_delay(&self) -> Duration {
+        self.subscription_delay
+    }
+}
@@ -0,0 +1,274 @@
+use crate::script::{Level, Script, Step};
+use futures::{SinkExt, StreamExt};
+use log::*;
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::{JoinHandle, JoinSet},
+    time::{sleep_until, Instant},
+};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{Request, Response},
+    protocol::Message,
+};
+
+/// Protocol a [MockExchange] speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Venue {
+    /// Combined streams, `/stream?streams=<symbol>@depth20@100ms/...`. Books are sent as `@depth20@100ms` snapshots
+    Binance,
+    /// `bts:subscribe` to `order_book_<pair>` channels. Books are sent on the `order_book_<pair>` channel
+    Bitstamp,
+}
+
+/// What the clients of a [MockServer] did so far
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Activity {
+    /// Connections accepted
+    pub connections: usize,
+    /// Request path of every connection, e.g. `/stream?streams=btcusdt@depth20@100ms`
+    pub requests: Vec<String>,
+    /// Text frames received, e.g. the subscriptions
+    pub received: Vec<String>,
+    /// Pongs received in answer to the pings
+    pub pongs: usize,
+}
+
+/// Local websocket server standing in for an exchange. Every connection plays a [Script],
+/// the scripts are handed out in the order they were added and the last one is repeated for the
+/// connections beyond them, so e.g. a disconnecting script followed by a streaming one tests a reconnection
+#[derive(Debug, Clone)]
+pub struct MockExchange {
+    venue: Venue,
+    scripts: Vec<Script>,
+}
+
+impl MockExchange {
+    pub fn new(venue: Venue) -> Self {
+        Self { venue, scripts: vec![] }
+    }
+
+    pub fn with_script(mut self, script: Script) -> Self {
+        self.scripts.push(script);
+        self
+    }
+
+    /// Listens on a free local port
+    pub async fn start(self) -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        info!("Mock {:?} exchange listening on {}", self.venue, addr);
+        let activity = Arc::new(Mutex::new(Activity::default()));
+        let task = tokio::spawn(accept(listener, self, activity.clone()));
+        Ok(MockServer { addr, activity, task })
+    }
+}
+
+/// A running [MockExchange], stopped along with its connections when dropped
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    activity: Arc<Mutex<Activity>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Websocket base url to point the connectors at, e.g. `ws://127.0.0.1:41234`
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    pub fn activity(&self) -> Activity {
+        self.activity.lock().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept(listener: TcpListener, exchange: MockExchange, activity: Arc<Mutex<Activity>>) {
+    // Dropping the set when the server is stopped aborts the connections
+    let mut connections = JoinSet::new();
+    let mut count = 0;
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                error!("Mock {:?} exchange couldn't accept a connection. Error: {}", exchange.venue, err);
+                continue;
+            }
+        };
+        let script = exchange
+            .scripts
+            .get(count)
+            .or_else(|| exchange.scripts.last())
+            .cloned()
+            .unwrap_or_default();
+        count += 1;
+        connections.spawn(serve(stream, exchange.venue, script, activity.clone()));
+        // Reaps the finished connections
+        while connections.try_join_next().is_some() {}
+    }
+}
+
+fn record(activity: &Mutex<Activity>, update: impl FnOnce(&mut Activity)) {
+    update(&mut activity.lock().unwrap_or_else(|err| err.into_inner()));
+}
+
+// The handshake callback's error type is tungstenite's
+#[allow(clippy::result_large_err)]
+async fn serve(stream: TcpStream, venue: Venue, script: Script, activity: Arc<Mutex<Activity>>) {
+    let mut path = String::new();
+    let ws = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response: Response| {
+        path = request.uri().to_string();
+        Ok(response)
+    })
+    .await;
+    let ws = match ws {
+        Ok(ws) => ws,
+        Err(err) => {
+            warn!("Mock {:?} exchange handshake failed. Error: {}", venue, err);
+            return;
+        }
+    };
+    record(&activity, |activity| {
+        activity.connections += 1;
+        activity.requests.push(path);
+    });
+
+    let (mut sink, mut source) = ws.split();
+    let subscription_delay = script.subscription_delay();
+    let mut steps = script.steps().to_vec().into_iter();
+    // When the next step is due, `None` before Bitstamp's first subscription and once the script is done
+    let mut due = match venue {
+        Venue::Binance => Some(Instant::now()),
+        Venue::Bitstamp => None,
+    };
+    let mut started = due.is_some();
+    let mut acks: VecDeque<(Instant, String)> = VecDeque::new();
+    let mut sequence: u64 = 0;
+    loop {
+        let next_ack = acks.front().map(|(at, _)| *at);
+        let msg = tokio::select! {
+            msg = source.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        if venue == Venue::Bitstamp {
+                            if let Some(ack) = subscription_ack(&text) {
+                                acks.push_back((Instant::now() + subscription_delay, ack));
+                            }
+                        }
+                        record(&activity, |activity| activity.received.push(text));
+                    }
+                    Some(Ok(Message::Pong(_))) => record(&activity, |activity| activity.pongs += 1),
+                    Some(Ok(Message::Close(_))) | None => return,
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => {
+                        debug!("Mock {:?} exchange connection failed. Error: {}", venue, err);
+                        return;
+                    }
+                }
+                continue;
+            }
+            _ = sleep_until(next_ack.unwrap_or_else(Instant::now)), if next_ack.is_some() => {
+                let Some((_, ack)) = acks.pop_front() else {
+                    continue;
+                };
+                if !started {
+                    started = true;
+                    due = Some(Instant::now());
+                }
+                Message::Text(ack)
+            }
+            _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                match steps.next() {
+                    Some(Step::Book { symbol, bids, asks }) => {
+                        sequence += 1;
+                        Message::Text(book_message(venue, &symbol, &bids, &asks, sequence))
+                    }
+                    Some(Step::Text(text)) => Message::Text(text),
+                    Some(Step::Binary(data)) => Message::Binary(data),
+                    Some(Step::Ping(data)) => Message::Ping(data),
+                    Some(Step::Wait(duration)) => {
+                        due = Some(Instant::now() + duration);
+                        continue;
+                    }
+                    Some(Step::Close) => {
+                        let _ = sink.send(Message::Close(None)).await;
+                        return;
+                    }
+                    Some(Step::Disconnect) => return,
+                    None => {
+                        due = None;
+                        continue;
+                    }
+                }
+            }
+        };
+        if let Err(err) = sink.send(msg).await {
+            debug!("Mock {:?} exchange couldn't send. Error: {}", venue, err);
+            return;
+        }
+    }
+}
+
+/// Acknowledgement of a `bts:subscribe` message, `None` for anything else
+fn subscription_ack(text: &str) -> Option<String> {
+    let msg = serde_json::from_str::<Value>(text).ok()?;
+    if msg.get("event").and_then(Value::as_str) != Some("bts:subscribe") {
+        return None;
+    }
+    let channel = msg.pointer("/data/channel").and_then(Value::as_str)?;
+    Some(
+        json!({
+            "event": "bts:subscription_succeeded",
+            "channel": channel,
+            "data": {}
+        })
+        .to_string(),
+    )
+}
+
+fn book_message(venue: Venue, symbol: &str, bids: &[Level], asks: &[Level], sequence: u64) -> String {
+    match venue {
+        Venue::Binance => json!({
+            "stream": format!("{}@depth20@100ms", symbol),
+            "data": {
+                "lastUpdateId": sequence,
+                "bids": bids,
+                "asks": asks
+            }
+        }),
+        Venue::Bitstamp => {
+            let micros = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros();
+            json!({
+                "event": "data",
+                "channel": format!("order_book_{}", symbol),
+                "data": {
+                    "timestamp": (micros / 1_000_000).to_string(),
+                    "microtimestamp": micros.to_string(),
+                    "bids": bids,
+                    "asks": asks
+                }
+            })
+        }
+    }
+    .to_string()
+}
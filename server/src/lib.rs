@@ -1,10 +1,10 @@
 
-use std::{collections::HashMap, pin::Pin, vec};
-use data_streamer::{model::OrderBook, Exchange};
-use float_ord::FloatOrd;
+use std::{cmp::Reverse, collections::HashMap, net::SocketAddr, pin::Pin, vec};
+use data_streamer::{model::{self, Decimal, OrderBook, OrderBookLevel}, Connection, Exchange};
+use rust_decimal::prelude::ToPrimitive;
 use futures::{Stream};
 use log::*;
-use tokio::sync::{mpsc, broadcast};
+use tokio::sync::{mpsc, broadcast, broadcast::error::{RecvError, TryRecvError}};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 pub mod orderbook {
@@ -12,10 +12,10 @@ pub mod orderbook {
 }
 
 use orderbook::orderbook_aggregator_server::OrderbookAggregator;
-use orderbook::{Level, Summary, Empty};
+use orderbook::{Level, Summary, Empty, Side, Substitution, Trade};
 
 /// Orderbook updates get stored in this struct
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct OrderbookStorage {
     pub data: HashMap<Exchange, OrderBook>
 }
@@ -29,56 +29,154 @@ impl OrderbookStorage {
         self.data.insert(exchange, orderbook);
     }
 
+    /// Drops the exchange's levels, e.g. when it's down
+    pub fn remove(&mut self, exchange: &Exchange) {
+        self.data.remove(exchange);
+    }
+
+    /// Drops the exchange's levels if they were streamed by `connection`, e.g. when it's lost.
+    /// Returns whether they were
+    pub fn remove_streamed_by(&mut self, exchange: &Exchange, connection: &Connection) -> bool {
+        let streamed = self
+            .data
+            .get(exchange)
+            .is_some_and(|ob| connection.streams_books_of(&ob.instrument));
+        if streamed {
+            self.data.remove(exchange);
+        }
+        streamed
+    }
+
     pub fn get(&self, key: &Exchange) -> Option<&OrderBook> {
         self.data.get(key)
     }
 
-    pub fn merge(&self) -> (f64, Vec<Level>, Vec<Level>){
-        let mut merged_bids: Vec<Level> = vec![];
-        let mut merged_asks: Vec<Level> = vec![];
-
-        for (ex, ob) in self.data.clone().into_iter() {
-            let mut bids: Vec<Level> = ob
-                .bids
-                .into_iter()
-                .map(|level| Level {
-                    exchange: ex.to_string(),
-                    price: level.price,
-                    amount: level.amount,
-                })
-                .collect();
-            merged_bids.append(&mut bids);
-
-            let mut asks: Vec<Level> = ob
-                .asks
-                .into_iter()
-                .map(|level| Level {
-                    exchange: ex.to_string(),
-                    price: level.price,
-                    amount: level.amount,
-                })
-                .collect();
-            merged_asks.append(&mut asks);
+    /// Spread and top levels of the merged books, `None` while either side is empty, e.g. a one-sided book
+    pub fn merge(&self) -> Option<(Decimal, Vec<Level>, Vec<Level>)> {
+        let mut merged_bids: Vec<(&OrderBook, &OrderBookLevel)> = vec![];
+        let mut merged_asks: Vec<(&OrderBook, &OrderBookLevel)> = vec![];
+
+        for ob in self.data.values() {
+            merged_bids.extend(ob.bids.iter().map(|level| (ob, level)));
+            merged_asks.extend(ob.asks.iter().map(|level| (ob, level)));
         }
-        merged_bids.sort_unstable_by_key(|level| (FloatOrd(-level.price), FloatOrd(-level.amount)));
-        merged_asks.sort_unstable_by_key(|level| (FloatOrd(level.price), FloatOrd(-level.amount)));
+        merged_bids.sort_unstable_by_key(|(_, level)| (Reverse(level.price), Reverse(level.amount)));
+        merged_asks.sort_unstable_by_key(|(_, level)| (level.price, Reverse(level.amount)));
         merged_bids.truncate(10);
         merged_asks.truncate(10);
 
-        let spread = merged_asks[0].price - merged_bids[0].price;
-
-        (spread, merged_bids, merged_asks)
+        let spread = merged_asks.first()?.1.price - merged_bids.first()?.1.price;
 
+        Some((spread, to_levels(merged_bids), to_levels(merged_asks)))
     }
 }
 
+/// Levels in the gRPC format, carrying both the doubles and the exact decimals,
+/// and the timestamps and substitutions of the book they come from
+fn to_levels(levels: Vec<(&OrderBook, &OrderBookLevel)>) -> Vec<Level> {
+    levels
+        .into_iter()
+        .map(|(ob, level)| Level {
+            exchange: ob.exchange.to_string(),
+            price: level.price.to_f64().unwrap_or_default(),
+            amount: level.amount.to_f64().unwrap_or_default(),
+            price_decimal: level.price.to_string(),
+            amount_decimal: level.amount.to_string(),
+            exchange_ts: ob.exchange_ts.map(|ts| ts.timestamp_micros()).unwrap_or_default(),
+            received_ts: ob.received_ts.timestamp_micros(),
+            substitutions: ob
+                .substitutions
+                .iter()
+                .map(|substitution| Substitution {
+                    from: substitution.from.clone(),
+                    to: substitution.to.clone(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
 
 /// The gRPC service
 #[derive(Debug)]
 pub struct OrderbookAggregatorService {
     pub sender: broadcast::Sender<Summary>,
     pub receiver: broadcast::Receiver<Summary>,
+    /// Trades from every venue, fanned out to the `TradeStream` clients
+    pub trades: broadcast::Sender<Trade>,
+}
+
+/// Messages broadcast to the streaming clients
+pub trait Broadcast: Clone + Send + 'static {
+    /// Whether a message supersedes the previous ones, a client that fell behind then skips to the latest
+    const SUPERSEDES: bool;
+
+    /// Tells the client how many messages it missed right before this one
+    fn set_skipped(&mut self, skipped: u64);
+}
+
+impl Broadcast for Summary {
+    const SUPERSEDES: bool = true;
+
+    fn set_skipped(&mut self, skipped: u64) {
+        self.skipped_summaries = skipped;
+    }
+}
+
+impl Broadcast for Trade {
+    const SUPERSEDES: bool = false;
+
+    fn set_skipped(&mut self, skipped: u64) {
+        self.skipped_trades = skipped;
+    }
 }
+
+/// Forwards what gets broadcast from now on to a client, until it disconnects. A client falling more than
+/// the broadcast capacity behind misses messages, the next one it gets carries how many
+fn forward<T: Broadcast>(
+    sender: &broadcast::Sender<T>,
+    remote_addr: Option<SocketAddr>,
+) -> ReceiverStream<Result<T, Status>> {
+    let (response_tx, response_rs) = mpsc::channel(16);
+    let mut watcher = sender.subscribe();
+    tokio::spawn(async move {
+        let mut skipped = 0;
+        loop {
+            let mut message = match watcher.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(missed)) => {
+                    skipped += missed;
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if skipped > 0 && T::SUPERSEDES {
+                // Catch up with the latest instead of working through the backlog
+                loop {
+                    match watcher.try_recv() {
+                        Ok(newer) => {
+                            message = newer;
+                            skipped += 1;
+                        }
+                        Err(TryRecvError::Lagged(missed)) => skipped += missed,
+                        Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                    }
+                }
+            }
+            if skipped > 0 {
+                warn!("Client {:?} fell behind, skipped {} messages", remote_addr, skipped);
+            }
+            message.set_skipped(skipped);
+            skipped = 0;
+            if response_tx.send(Ok(message)).await.is_err() {
+                info!("Client {:?} disconnected", remote_addr);
+                break
+            }
+        }
+    });
+    ReceiverStream::new(response_rs)
+}
+
 /// Handling incoming requests for the defined gRPC endpoint
 #[tonic::async_trait]
 impl OrderbookAggregator for OrderbookAggregatorService {
@@ -89,27 +187,52 @@ impl OrderbookAggregator for OrderbookAggregatorService {
     ) -> Result<Response<Self::BookSummaryStream>, Status> {
         let remote_addr = request.remote_addr();
         info!("Received a new request from {:?}", remote_addr);
-        let (response_tx, response_rs) = mpsc::channel(16);
-        let mut watcher = self.sender.subscribe();
-        tokio::spawn(async move {
-            while let Ok(message) = watcher.recv().await {
-                if response_tx.send(Ok(message)).await.is_err() {
-                    info!("Client {:?} disconnected", remote_addr);
-                    break
-                }
-            }
-        });
-        Ok(Response::new(Box::pin(ReceiverStream::new(response_rs))))
+        Ok(Response::new(Box::pin(forward(&self.sender, remote_addr))))
+    }
+
+    type TradeStreamStream = Pin<Box<dyn Stream<Item = Result<Trade, Status>> + Send + 'static>>;
+    async fn trade_stream(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<Self::TradeStreamStream>, Status> {
+        let remote_addr = request.remote_addr();
+        info!("Received a new trade stream request from {:?}", remote_addr);
+        Ok(Response::new(Box::pin(forward(&self.trades, remote_addr))))
     }
 }
 
 /// Response sent to the client. Transform the current `OrderbookStorage` into the desired format `Summary`
-impl From<(f64, Vec<Level>, Vec<Level>)> for Summary {
-    fn from((spread, merged_bids, merged_asks): (f64, Vec<Level>, Vec<Level>)) -> Self {
+impl From<(Decimal, Vec<Level>, Vec<Level>)> for Summary {
+    fn from((spread, merged_bids, merged_asks): (Decimal, Vec<Level>, Vec<Level>)) -> Self {
         Self {
-            spread,
+            spread: spread.to_f64().unwrap_or_default(),
             bids: merged_bids,
             asks: merged_asks,
+            spread_decimal: spread.to_string(),
+            skipped_summaries: 0,
+            skipped_events: 0,
+        }
+    }
+}
+
+/// Trade sent to the `TradeStream` clients
+impl From<model::Trade> for Trade {
+    fn from(trade: model::Trade) -> Self {
+        Self {
+            exchange: trade.exchange.to_string(),
+            instrument: trade.instrument,
+            price: trade.price.to_f64().unwrap_or_default(),
+            amount: trade.amount.to_f64().unwrap_or_default(),
+            price_decimal: trade.price.to_string(),
+            amount_decimal: trade.amount.to_string(),
+            side: match trade.side {
+                model::Side::Buy => Side::Buy,
+                model::Side::Sell => Side::Sell,
+            } as i32,
+            trade_id: trade.trade_id,
+            exchange_ts: trade.exchange_ts.map(|ts| ts.timestamp_micros()).unwrap_or_default(),
+            received_ts: trade.received_ts.timestamp_micros(),
+            skipped_trades: 0,
         }
     }
 }
@@ -1,12 +1,13 @@
 use server::{
-    orderbook::{orderbook_aggregator_server::OrderbookAggregatorServer, Summary}, OrderbookAggregatorService,
+    orderbook::{orderbook_aggregator_server::OrderbookAggregatorServer, Summary, Trade}, OrderbookAggregatorService,
 };
-use data_streamer::{Exchange, Instrument, Subscriptions};
+use data_streamer::{BookMode, BybitCategory, CatalogueFetcher, Delivery, Exchange, ExchangeEndpoints, Recorder, RecorderConfig, ReplayExchange, ReplaySpeed, StreamEvent, Instrument, Subscriptions, SymbolRegistry};
 use log::*;
 use tonic::transport::{Server};
-use tokio::sync::{broadcast};
+use tokio::sync::{broadcast, broadcast::error::{RecvError, TryRecvError}};
 use server::{OrderbookStorage};
 use clap::Parser;
+use std::time::Duration;
 #[derive(Parser, Debug, Clone)]
 #[clap(author = "Eduardo Gallego", version = "0.0", about = "Universal Orderbook", long_about = None)]
 struct Args {
@@ -14,6 +15,58 @@ struct Args {
     base: String,
     #[clap(short, long, help = "The quote of the pair")]
     quote: String,
+    #[clap(
+        short,
+        long,
+        use_value_delimiter = true,
+        default_value = "Binance,Bitstamp,Coinbase",
+        help = "Comma separated exchanges to merge"
+    )]
+    exchanges: Vec<Exchange>,
+    #[clap(long, help = "Maintain full depth books from diff streams instead of partial snapshots")]
+    diff: bool,
+    #[clap(long, default_value = "spot", help = "Bybit market to stream: spot or linear (USDT perpetuals)")]
+    bybit_category: BybitCategory,
+    #[clap(
+        long,
+        default_value = "30",
+        help = "Reconnect exchanges without a book update for this many seconds, 0 disables it"
+    )]
+    stale_after: u64,
+    #[clap(long, help = "TOML file with per exchange symbol substitutions, on top of the built-in ones")]
+    symbols: Option<String>,
+    #[clap(long, help = "TOML file with per exchange websocket and REST urls, UOS_<EXCHANGE>_WS_URL/_REST_URL override it")]
+    endpoints: Option<String>,
+    #[clap(long, help = "Stream without checking the pair is listed on the exchanges")]
+    skip_validation: bool,
+    #[clap(long, help = "Stream the trades too, served through the TradeStream RPC")]
+    trades: bool,
+    #[clap(long, help = "Directory to record the raw websocket frames to")]
+    record: Option<String>,
+    #[clap(
+        long,
+        use_value_delimiter = true,
+        requires = "record",
+        help = "Comma separated exchanges to record, all of them if missing"
+    )]
+    record_exchanges: Option<Vec<Exchange>>,
+    #[clap(long, help = "Recording file or directory to replay instead of connecting to the exchanges")]
+    replay: Option<String>,
+    #[clap(
+        long,
+        default_value = "realtime",
+        requires = "replay",
+        help = "Replay pacing: realtime, an acceleration like x10 or max"
+    )]
+    replay_speed: ReplaySpeed,
+    #[clap(long, help = "Merge the latest book of every exchange instead of queueing each update")]
+    conflate: bool,
+    #[clap(long, default_value = "16", help = "Exchange events buffered for the merging, beyond it the oldest are skipped")]
+    events_capacity: usize,
+    #[clap(long, default_value = "16", help = "Summaries buffered per client, beyond it a client skips to the latest")]
+    summary_capacity: usize,
+    #[clap(long, default_value = "64", help = "Trades buffered per client, beyond it a client misses the oldest")]
+    trades_capacity: usize,
 }
 
 #[tokio::main]
@@ -26,23 +79,104 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let address = "[::1]:10000";
     info!("Server listening on {address}");
-    let subscriptions = Subscriptions {
-        instrument: Instrument {
+    let symbols = match &args.symbols {
+        Some(path) => SymbolRegistry::from_file(path)?,
+        None => SymbolRegistry::default(),
+    };
+    let endpoints = match &args.endpoints {
+        Some(path) => ExchangeEndpoints::from_file(path)?.with_env(),
+        None => ExchangeEndpoints::from_env(),
+    };
+    let mut subscriptions = Subscriptions::new(
+        vec![Instrument {
             base: args.base.to_string(),
             quote: args.quote.to_string(),
-        },
-        exchanges: vec![Exchange::Binance, Exchange::Bitstamp],
-    };
+        }],
+        args.exchanges.clone(),
+    )
+    .with_book_mode(if args.diff { BookMode::Diff } else { BookMode::Snapshot })
+    .with_bybit_category(args.bybit_category)
+    .with_trades(args.trades)
+    .with_stale_after(Some(Duration::from_secs(args.stale_after)).filter(|stale_after| !stale_after.is_zero()))
+    .with_symbol_registry(symbols)
+    .with_endpoints(endpoints)
+    .with_catalogue((!args.skip_validation).then(CatalogueFetcher::new));
+    if let Some(dir) = &args.record {
+        let mut config = RecorderConfig::new(dir);
+        if let Some(exchanges) = &args.record_exchanges {
+            config = config.with_exchanges(exchanges.clone());
+        }
+        subscriptions = subscriptions.with_recorder(Recorder::start(config)?);
+    }
+    if let Some(path) = &args.replay {
+        // Replays run offline, the listings can't be fetched
+        subscriptions = subscriptions
+            .with_catalogue(None)
+            .with_replay(ReplayExchange::new(path)?.with_speed(args.replay_speed));
+    }
 
     let mut orderbook_storage = OrderbookStorage::new();
-    let (tx, receiver) = broadcast::channel(16);
+    let (tx, receiver) = broadcast::channel(args.summary_capacity.max(1));
     let sender = tx.clone();
+    let (trade_tx, _) = broadcast::channel(args.trades_capacity.max(1));
+    let trades = trade_tx.clone();
     
+    let mut streams = subscriptions
+        .with_capacity(args.events_capacity)
+        .with_delivery(if args.conflate { Delivery::Conflated } else { Delivery::Queued })
+        .init()
+        .await?;
+    let mut books = streams.watch_books();
     tokio::spawn(async move {
-        let mut streams = subscriptions.clone().init().await.unwrap();
-        while let Ok(orderbook) = streams.stream.recv().await {
-            orderbook_storage.update(orderbook.exchange, orderbook);
-            let summary = Summary::from(orderbook_storage.merge());
+        // Events the merging missed since the last summary, and since the start
+        let mut skipped_events = 0;
+        let mut total_skipped = 0;
+        loop {
+            let mut changed = tokio::select! {
+                // Books first: a book published before a disconnection is applied before the venue gets removed
+                biased;
+                updated = async { books.as_mut().unwrap().changed().await }, if books.is_some() => match updated {
+                    Some(updated) => {
+                        for orderbook in updated {
+                            orderbook_storage.update(orderbook.exchange.clone(), orderbook);
+                        }
+                        true
+                    }
+                    None => break,
+                },
+                event = streams.stream.recv() => match event {
+                    Ok(event) => apply(&mut orderbook_storage, event, &trade_tx),
+                    Err(RecvError::Lagged(missed)) => {
+                        skipped_events += missed;
+                        total_skipped += missed;
+                        warn!("Merging fell behind, skipped {} events ({} in total)", missed, total_skipped);
+                        // Skip to the latest books, applying what's buffered without merging in between
+                        let mut changed = false;
+                        loop {
+                            match streams.stream.try_recv() {
+                                Ok(event) => changed |= apply(&mut orderbook_storage, event, &trade_tx),
+                                Err(TryRecvError::Lagged(missed)) => {
+                                    skipped_events += missed;
+                                    total_skipped += missed;
+                                }
+                                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                            }
+                        }
+                        changed
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            changed &= !orderbook_storage.data.is_empty();
+            if !changed {
+                continue;
+            }
+            // No spread without both sides, the skipped events carry over to the next summary
+            let Some(merged) = orderbook_storage.merge() else {
+                continue;
+            };
+            let mut summary = Summary::from(merged);
+            summary.skipped_events = std::mem::take(&mut skipped_events);
             if tx.send(summary).is_err() {
                 warn!("Connection dropped");
             };
@@ -52,6 +186,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let service = OrderbookAggregatorService {
         sender,
         receiver,
+        trades,
     };
 
     Server::builder()
@@ -61,3 +196,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Applies a streamer event to the storage. Returns whether the merged books changed
+fn apply(orderbook_storage: &mut OrderbookStorage, event: StreamEvent, trade_tx: &broadcast::Sender<Trade>) -> bool {
+    match event {
+        StreamEvent::Book(orderbook) => orderbook_storage.update(orderbook.exchange.clone(), orderbook),
+        StreamEvent::Trade(trade) => {
+            // Fails when no client is streaming trades
+            let _ = trade_tx.send(Trade::from(trade));
+            return false;
+        }
+        // Stop merging the venue's last known levels, they are stale until its book connection is back.
+        // Its trade connection or the shards of other instruments don't affect them
+        StreamEvent::Disconnected { exchange, connection, reason } => {
+            warn!("{} {} disconnected: {}", exchange, connection, reason);
+            if !orderbook_storage.remove_streamed_by(&exchange, &connection) {
+                return false;
+            }
+            warn!("Excluding {}", exchange);
+        }
+        StreamEvent::Stale { exchange, connection, instrument, silent_for } => {
+            let silent = instrument.as_deref().unwrap_or("any instrument");
+            warn!("No {} {} update of {} for {:?}", exchange, connection, silent, silent_for);
+            // The connection gets re-established, its other instruments are dropped once it's disconnected
+            let stale = orderbook_storage
+                .get(&exchange)
+                .is_some_and(|ob| instrument.as_ref().is_none_or(|instrument| *instrument == ob.instrument));
+            if !stale || !orderbook_storage.remove_streamed_by(&exchange, &connection) {
+                return false;
+            }
+            warn!("Excluding {}", exchange);
+        }
+        StreamEvent::Down { exchange, connection, failures } => {
+            warn!("{} {} is down after {} failures", exchange, connection, failures);
+            return false;
+        }
+        StreamEvent::Connected { exchange, connection } | StreamEvent::Up { exchange, connection } => {
+            info!("{} {} is up", exchange, connection);
+            return false;
+        }
+        StreamEvent::Subscribed { exchange, channels } => {
+            info!("Subscribed to {} {:?}", exchange, channels);
+            return false;
+        }
+        StreamEvent::Reconnecting { exchange, connection, attempt, delay } => {
+            info!("Reconnecting to {} {} in {:?}, attempt {}", exchange, connection, delay, attempt);
+            return false;
+        }
+        StreamEvent::Error(err) => {
+            error!("{}", err);
+            return false;
+        }
+    }
+    true
+}